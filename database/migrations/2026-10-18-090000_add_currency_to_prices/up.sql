@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+ALTER TABLE prices
+ADD COLUMN currency TEXT;
+
+-- Every shop tracked so far was polish
+UPDATE prices
+SET currency = 'PLN'
+WHERE value IS NOT NULL;
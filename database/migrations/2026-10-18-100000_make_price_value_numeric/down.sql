@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE prices
+ALTER COLUMN value TYPE FLOAT8;
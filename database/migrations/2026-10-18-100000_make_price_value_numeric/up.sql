@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- Prices were scraped with two decimal places, rounding drops float artifacts like 1299.9899999
+ALTER TABLE prices
+ALTER COLUMN value TYPE NUMERIC USING ROUND(value::NUMERIC, 2);
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+-- Prices which changed a lot since the last trusted one,
+-- they don't trigger notifications until they are confirmed
+ALTER TABLE prices
+ADD COLUMN suspicious BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,29 @@
+-- This file should undo anything in `up.sql`
+
+-- Values can't be removed from an enum, the type is created again without them
+ALTER TABLE prices ALTER COLUMN availability DROP DEFAULT;
+
+ALTER TYPE availability RENAME TO availability_old;
+
+CREATE TYPE availability AS ENUM (
+    'available',
+    'temporarily_unavailable',
+    'unavailable',
+    'price_not_found',
+    'site_not_found'
+);
+
+ALTER TABLE prices
+ALTER COLUMN availability TYPE availability
+USING (
+    CASE availability::TEXT
+        WHEN 'blocked' THEN 'unavailable'
+        WHEN 'server_error' THEN 'unavailable'
+        WHEN 'rate_limited' THEN 'unavailable'
+        ELSE availability::TEXT
+    END
+)::availability;
+
+ALTER TABLE prices ALTER COLUMN availability SET DEFAULT 'available';
+
+DROP TYPE availability_old;
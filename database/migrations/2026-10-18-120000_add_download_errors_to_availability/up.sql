@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+ALTER TYPE availability ADD VALUE 'blocked';
+ALTER TYPE availability ADD VALUE 'server_error';
+ALTER TYPE availability ADD VALUE 'rate_limited';
@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX offers_next_check_at_idx;
+
+ALTER TABLE offers
+DROP COLUMN next_check_at,
+DROP COLUMN check_interval;
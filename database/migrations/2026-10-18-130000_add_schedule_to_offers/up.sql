@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+-- Every offer is checked on its own schedule, the interval grows
+-- while the offer doesn't change and drops back when it does
+ALTER TABLE offers
+ADD COLUMN next_check_at TIMESTAMP NOT NULL DEFAULT NOW(),
+ADD COLUMN check_interval INTEGER NOT NULL DEFAULT 3600;
+
+CREATE INDEX offers_next_check_at_idx ON offers (next_check_at);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE offers
+DROP COLUMN claimed_by,
+DROP COLUMN claimed_until;
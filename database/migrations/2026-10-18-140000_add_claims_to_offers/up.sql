@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+-- Offers being checked are claimed by one instance of the scraper,
+-- the claim runs out when the instance dies without releasing it
+ALTER TABLE offers
+ADD COLUMN claimed_by TEXT,
+ADD COLUMN claimed_until TIMESTAMP;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- Downloads which failed or timed out were recorded as unavailable,
+-- which looked like the shop ran out of the product
+ALTER TYPE availability ADD VALUE 'download_failed';
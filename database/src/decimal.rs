@@ -0,0 +1,71 @@
+use std::fmt::Display;
+use std::io::Write;
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Numeric;
+use juniper::parser::{ParseError, ScalarToken, Token};
+use juniper::{ParseScalarResult, Value};
+
+/// Exact decimal number stored as NUMERIC, so prices don't get f64 rounding artifacts.
+/// In GraphQL it's a string, e.g. "1299.99", because JSON numbers are floats.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, AsExpression, FromSqlRow)]
+#[sql_type = "Numeric"]
+pub struct Decimal(pub BigDecimal);
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = bigdecimal::ParseBigDecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BigDecimal::from_str(s).map(Decimal)
+    }
+}
+
+impl From<BigDecimal> for Decimal {
+    fn from(value: BigDecimal) -> Self {
+        Decimal(value)
+    }
+}
+
+impl ToSql<Numeric, Pg> for Decimal {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<Numeric, Pg>::to_sql(&self.0, out)
+    }
+}
+
+impl FromSql<Numeric, Pg> for Decimal {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        <BigDecimal as FromSql<Numeric, Pg>>::from_sql(bytes).map(Decimal)
+    }
+}
+
+#[juniper::graphql_scalar(description = "Exact decimal number as a string. E.g. \"1299.99\"")]
+impl<S> GraphQLScalar for Decimal
+where
+    S: ScalarValue,
+{
+    fn resolve(&self) -> Value {
+        Value::scalar(self.0.to_string())
+    }
+
+    fn from_input_value(v: &InputValue) -> Option<Decimal> {
+        v.as_string_value().and_then(|s| s.parse().ok())
+    }
+
+    fn from_str<'a>(value: ScalarToken<'a>) -> ParseScalarResult<'a, S> {
+        if let ScalarToken::String(value) = value {
+            Ok(S::from(value.to_owned()))
+        } else {
+            Err(ParseError::UnexpectedToken(Token::Scalar(value)))
+        }
+    }
+}
@@ -28,6 +28,10 @@ table! {
     offers (id) {
         id -> Int4,
         url -> Text,
+        next_check_at -> Timestamp,
+        check_interval -> Int4,
+        claimed_by -> Nullable<Text>,
+        claimed_until -> Nullable<Timestamp>,
     }
 }
 
@@ -35,9 +39,11 @@ table! {
     prices (id) {
         id -> Int4,
         offer_id -> Int4,
-        value -> Nullable<Float8>,
+        value -> Nullable<Numeric>,
         created_at -> Timestamp,
         availability -> crate::models::price::AvailabilityMapping,
+        currency -> Nullable<Text>,
+        suspicious -> Bool,
     }
 }
 
@@ -3,5 +3,6 @@ extern crate diesel;
 
 pub mod context;
 pub mod db;
+pub mod decimal;
 pub mod diesel_schema;
 pub mod models;
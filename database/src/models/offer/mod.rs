@@ -12,6 +12,13 @@ use crate::{context::GraphQLContext, models::price::Price};
 pub struct Offer {
     pub id: i32,
     pub url: String,
+    /// When the scraper should check the offer again
+    pub next_check_at: chrono::NaiveDateTime,
+    /// Seconds between checks, grows while the offer doesn't change
+    pub check_interval: i32,
+    /// Instance of the scraper checking the offer right now
+    pub claimed_by: Option<String>,
+    pub claimed_until: Option<chrono::NaiveDateTime>,
 }
 
 #[juniper::graphql_object(context = GraphQLContext)]
@@ -24,6 +31,10 @@ impl Offer {
         &self.url
     }
 
+    pub fn next_check_at(&self) -> chrono::NaiveDateTime {
+        self.next_check_at
+    }
+
     pub async fn products(&self, context: &GraphQLContext) -> FieldResult<Vec<Product>> {
         let conn = context.pool.get()?;
         queries::get_products_of_offer(&conn, self.id)
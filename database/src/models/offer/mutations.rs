@@ -1,5 +1,9 @@
+use diesel::BoolExpressionMethods;
+use diesel::Connection;
 use diesel::ExpressionMethods;
+use diesel::OptionalExtension;
 use diesel::PgConnection;
+use diesel::QueryDsl;
 use diesel::RunQueryDsl;
 use juniper::FieldResult;
 
@@ -24,6 +28,82 @@ pub fn change_url(conn: &PgConnection, id: i32, new_value: String) -> FieldResul
     utils::graphql_translate(res)
 }
 
+/// Claims offers due to be checked for the worker, the longest waiting first. Rows locked
+/// by other workers are skipped, so instances of the scraper never take the same offer.
+/// The claim runs out after `lease_secs`, then the offer is due for anyone again.
+pub fn claim_due_offers(
+    conn: &PgConnection,
+    worker: &str,
+    limit: i64,
+    lease_secs: i64,
+) -> FieldResult<Vec<Offer>> {
+    let now = chrono::Utc::now().naive_utc();
+
+    let res = conn.transaction(|| {
+        let due: Vec<i32> = offers::table
+            .select(offers::columns::id)
+            .filter(offers::columns::next_check_at.le(now))
+            .filter(
+                offers::columns::claimed_until
+                    .is_null()
+                    .or(offers::columns::claimed_until.lt(now)),
+            )
+            .order(offers::columns::next_check_at.asc())
+            .limit(limit)
+            .for_update()
+            .skip_locked()
+            .load(conn)?;
+
+        diesel::update(offers::table)
+            .filter(offers::columns::id.eq_any(due))
+            .set((
+                offers::columns::claimed_by.eq(worker),
+                offers::columns::claimed_until.eq(now + chrono::Duration::seconds(lease_secs)),
+            ))
+            .get_results(conn)
+    });
+
+    utils::graphql_translate(res)
+}
+
+/// Plans the next check and releases the claim. Nothing happens when the claim ran out
+/// and another worker took the offer, its check plans the offer then.
+pub fn schedule_next_check(
+    conn: &PgConnection,
+    id: i32,
+    worker: &str,
+    check_interval: i32,
+    next_check_at: chrono::NaiveDateTime,
+) -> FieldResult<Option<Offer>> {
+    let res = diesel::update(offers::table)
+        .filter(offers::columns::id.eq(id))
+        .filter(offers::columns::claimed_by.eq(worker))
+        .set((
+            offers::columns::check_interval.eq(check_interval),
+            offers::columns::next_check_at.eq(next_check_at),
+            offers::columns::claimed_by.eq(None::<String>),
+            offers::columns::claimed_until.eq(None::<chrono::NaiveDateTime>),
+        ))
+        .get_result(conn)
+        .optional();
+
+    utils::graphql_translate(res)
+}
+
+/// Releases all offers claimed by the worker, e.g. left by its last run which crashed.
+/// They are due right away, if they were due when claimed. Returns how many there were.
+pub fn release_claims(conn: &PgConnection, worker: &str) -> FieldResult<usize> {
+    let res = diesel::update(offers::table)
+        .filter(offers::columns::claimed_by.eq(worker))
+        .set((
+            offers::columns::claimed_by.eq(None::<String>),
+            offers::columns::claimed_until.eq(None::<chrono::NaiveDateTime>),
+        ))
+        .execute(conn);
+
+    utils::graphql_translate(res)
+}
+
 pub fn delete_offer(conn: &PgConnection, id: i32) -> FieldResult<Offer> {
     let res = diesel::delete(offers::table)
         .filter(offers::columns::id.eq(id))
@@ -1,7 +1,7 @@
 use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
 use juniper::FieldResult;
 
-use crate::diesel_schema::{offers, products, products_offers_relation};
+use crate::diesel_schema::{notifications, offers, products, products_offers_relation};
 use crate::models::offer::Offer;
 use crate::models::product::Product;
 use crate::models::utils;
@@ -40,3 +40,14 @@ pub fn get_offer_by_url(conn: &PgConnection, url: &str) -> Option<Offer> {
         .get_result::<Offer>(conn)
         .ok()
 }
+
+/// Someone gets notified about a product of the offer.
+pub fn is_offer_watched(conn: &PgConnection, offer_id: i32) -> FieldResult<bool> {
+    let res = diesel::select(diesel::dsl::exists(
+        products_offers_relation::table
+            .inner_join(products::table.inner_join(notifications::table))
+            .filter(products_offers_relation::columns::offer_id.eq(offer_id)),
+    ))
+    .get_result(conn);
+    utils::graphql_translate(res)
+}
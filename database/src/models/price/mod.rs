@@ -8,6 +8,7 @@ use juniper::{graphql_object, FieldResult};
 
 use crate::{
     context::GraphQLContext,
+    decimal::Decimal,
     diesel_schema::prices,
     models::{self, offer::Offer},
 };
@@ -20,6 +21,26 @@ pub enum Availability {
     Unavailable,
     PriceNotFound,
     SiteNotFound,
+    /// The shop refused to serve the page, e.g. with a captcha
+    Blocked,
+    /// The shop failed, e.g. during maintenance
+    ServerError,
+    /// The shop asked to slow down
+    RateLimited,
+    /// The page couldn't be downloaded, e.g. it timed out
+    DownloadFailed,
+}
+
+impl Availability {
+    /// Tells what the shop says about the product. Other states are failures of scraping.
+    pub fn is_stock_state(self) -> bool {
+        matches!(
+            self,
+            Availability::Available
+                | Availability::TemporarilyUnavailable
+                | Availability::Unavailable
+        )
+    }
 }
 
 impl Display for Availability {
@@ -32,9 +53,14 @@ impl Display for Availability {
 pub struct Price {
     pub id: i32,
     pub offer_id: i32,
-    pub value: Option<f64>,
+    pub value: Option<Decimal>,
     pub created_at: chrono::NaiveDateTime,
     pub availability: Availability,
+    /// ISO 4217 code. E.g. "PLN"
+    pub currency: Option<String>,
+    /// Changed a lot since the last trusted price, so it might be an error of scraping.
+    /// It's trusted again when confirmed by the next scrape or by a user
+    pub suspicious: bool,
 }
 
 #[graphql_object(context = GraphQLContext)]
@@ -43,8 +69,8 @@ impl Price {
         self.id
     }
 
-    pub fn value(&self) -> Option<f64> {
-        self.value
+    pub fn value(&self) -> Option<&Decimal> {
+        self.value.as_ref()
     }
 
     pub fn created_at(&self) -> chrono::NaiveDateTime {
@@ -59,14 +85,25 @@ impl Price {
     pub fn availability(&self) -> Availability {
         self.availability
     }
+
+    pub fn currency(&self) -> Option<&str> {
+        self.currency.as_deref()
+    }
+
+    pub fn suspicious(&self) -> bool {
+        self.suspicious
+    }
 }
 
 // TODO: This is probably not needed for users outside
 // The GraphQL input object for creating PRICESs
-#[derive(juniper::GraphQLInputObject, Insertable, Debug)]
+#[derive(juniper::GraphQLInputObject, Insertable, Clone, Debug)]
 #[table_name = "prices"]
 pub struct CreatePriceInput {
     pub offer_id: i32,
-    pub value: Option<f64>,
+    pub value: Option<Decimal>,
     pub availability: Availability,
+    pub currency: Option<String>,
+    #[graphql(default)]
+    pub suspicious: bool,
 }
@@ -1,4 +1,4 @@
-use diesel::{PgConnection, RunQueryDsl};
+use diesel::{ExpressionMethods, PgConnection, RunQueryDsl};
 use juniper::FieldResult;
 
 use crate::diesel_schema::prices;
@@ -12,3 +12,12 @@ pub fn create_price(conn: &PgConnection, new_price: &CreatePriceInput) -> FieldR
 
     utils::graphql_translate(res)
 }
+
+pub fn confirm_price(conn: &PgConnection, id: i32) -> FieldResult<Price> {
+    let res = diesel::update(prices::table)
+        .filter(prices::columns::id.eq(id))
+        .set(prices::columns::suspicious.eq(false))
+        .get_result(conn);
+
+    utils::graphql_translate(res)
+}
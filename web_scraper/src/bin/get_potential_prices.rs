@@ -18,6 +18,7 @@ async fn main() {
     let scraper = PriceScraper::new(config).await;
 
     let potential_prices_result = scraper.get_potential_prices_blocks(&url).await;
+    scraper.close().await;
 
     let potential_prices = match potential_prices_result {
         Ok(v) => v,
@@ -0,0 +1,114 @@
+use std::path::Path;
+
+use web_scraper::config::PriceScraperConfig;
+use web_scraper::downloaders::fixture::{
+    load_manifest, page_file_name, save_manifest, Fixture, FixtureDownloader,
+};
+use web_scraper::price_scraper::PriceScraper;
+use web_scraper::utils::init_env_and_logging;
+
+/// Saves the pages into tests/fixtures and adds them to the manifest with what the scraper finds on them.
+/// Several urls can be given, e.g. one product page of every shop.
+/// Check the expected values in the manifest by hand before committing them.
+#[tokio::main()]
+async fn main() {
+    init_env_and_logging();
+
+    let urls: Vec<String> = std::env::args().skip(1).collect();
+    if urls.is_empty() {
+        eprintln!("Required positional arguments => page_url: string, ...");
+        return;
+    }
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let config = PriceScraperConfig::default();
+
+    let scraper = PriceScraper::new(config.clone()).await;
+    for url in &urls {
+        record(&scraper, &config, &dir, url).await;
+    }
+    scraper.close().await;
+}
+
+async fn record(scraper: &PriceScraper, config: &PriceScraperConfig, dir: &Path, url: &str) {
+    let parsed_url = match url::Url::parse(url) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("Not valid url: {}. Cause: {:?}", url, err);
+            return;
+        }
+    };
+
+    let page = match scraper.download_page(url).await {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("Error occured while downloading the page: {}", url);
+            eprintln!("{:?}", err);
+            return;
+        }
+    };
+
+    let file_name = page_file_name(&parsed_url);
+    if let Err(err) = std::fs::write(dir.join(&file_name), page.html) {
+        eprintln!("Couldn't save the page: {:?}", err);
+        return;
+    }
+
+    let mut fixtures = match load_manifest(dir) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("{:?}", err);
+            return;
+        }
+    };
+    fixtures.retain(|fixture| fixture.url != url);
+    fixtures.push(Fixture {
+        url: url.to_owned(),
+        page: file_name,
+        price: None,
+        currency: None,
+        availability: String::new(),
+    });
+    if let Err(err) = save_manifest(dir, &fixtures) {
+        eprintln!("{:?}", err);
+        return;
+    }
+
+    // Scrape the saved page, so the expected values are the ones the scraper sees now
+    let downloader = match FixtureDownloader::from_dir(dir) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("{:?}", err);
+            return;
+        }
+    };
+    let fixture_scraper = PriceScraper::new(config.clone())
+        .await
+        .with_downloader(Box::new(downloader));
+
+    let scraped = fixture_scraper.get_price_once(url).await;
+    fixture_scraper.close().await;
+    let scraped = match scraped {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!(
+                "Page of {} saved, but the price couldn't be scraped. Fill the manifest by hand",
+                url
+            );
+            eprintln!("{:?}", err);
+            return;
+        }
+    };
+
+    if let Some(fixture) = fixtures.last_mut() {
+        fixture.price = scraped.value.map(|v| v.to_string());
+        fixture.currency = scraped.currency;
+        fixture.availability = scraped.availability.to_string();
+    }
+    if let Err(err) = save_manifest(dir, &fixtures) {
+        eprintln!("{:?}", err);
+        return;
+    }
+
+    println!("Recorded {}", fixtures.last().map_or("", |v| &v.page));
+}
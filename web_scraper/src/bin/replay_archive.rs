@@ -0,0 +1,55 @@
+use web_scraper::config::PriceScraperConfig;
+use web_scraper::downloaders::archive::PageArchive;
+use web_scraper::downloaders::replay::ReplayDownloader;
+use web_scraper::price_scraper::PriceScraper;
+use web_scraper::utils::init_env_and_logging;
+
+/// Scrapes again every page of the archive with the current settings,
+/// so fixed selectors can be checked without downloading pages from shops.
+#[tokio::main()]
+async fn main() {
+    init_env_and_logging();
+
+    let mut config = PriceScraperConfig::default();
+
+    let archive_dir = match std::env::args()
+        .nth(1)
+        .or_else(|| config.archive.as_ref().map(|archive| archive.dir.clone()))
+    {
+        Some(v) => v,
+        None => {
+            eprintln!("Required first positional argument => archive_dir: string, or archive set in the settings");
+            return;
+        }
+    };
+
+    let downloader = match ReplayDownloader::new(PageArchive::new(&archive_dir)) {
+        Ok(v) => v,
+        Err(err) => {
+            eprintln!("{:?}", err);
+            return;
+        }
+    };
+
+    let mut urls: Vec<String> = downloader.urls().map(str::to_owned).collect();
+    urls.sort();
+
+    // Replayed pages are already in the archive
+    config.archive = None;
+    let scraper = PriceScraper::new(config)
+        .await
+        .with_downloader(Box::new(downloader));
+
+    for url in urls {
+        match scraper.get_price_once(&url).await {
+            Ok(price) => println!(
+                "{} => {} {} {}",
+                url,
+                price.value.map(|v| v.to_string()).unwrap_or_default(),
+                price.currency.unwrap_or_default(),
+                price.availability
+            ),
+            Err(err) => println!("{} => {:?}", url, err),
+        }
+    }
+}
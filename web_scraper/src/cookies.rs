@@ -0,0 +1,225 @@
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use cookie_store::{CookieDomain, CookieStore, RawCookie};
+use reqwest::header::HeaderValue;
+
+use crate::config::DomainRule;
+use crate::utils::write_atomically;
+
+#[derive(thiserror::Error, Debug)]
+#[error("Couldn't read or write cookies")]
+pub struct CookieJarError;
+
+/// Cookies of all shops, kept apart by their domains like a browser does.
+/// Reqwest reads and updates it on every request, browser sessions get the cookies
+/// before opening a page and give them back after.
+pub struct CookieJar {
+    store: RwLock<CookieStore>,
+    /// Where the cookies are saved between runs
+    file: Option<PathBuf>,
+}
+
+impl CookieJar {
+    /// Loads cookies saved in the file, then sets the cookies configured for shops.
+    pub fn load(file: Option<PathBuf>, rules: &[DomainRule]) -> Self {
+        let store = match &file {
+            Some(path) if path.exists() => std::fs::File::open(path)
+                .map_err(cookie_store::Error::from)
+                .and_then(|file| CookieStore::load_json(BufReader::new(file)))
+                .unwrap_or_else(|error| {
+                    log::warn!(
+                        "Couldn't load cookies, starting without them. Path: {:?}. Cause: {:?}",
+                        path,
+                        error
+                    );
+                    CookieStore::default()
+                }),
+            _ => CookieStore::default(),
+        };
+
+        let jar = Self {
+            store: RwLock::new(store),
+            file,
+        };
+        for rule in rules {
+            jar.seed(rule);
+        }
+        jar
+    }
+
+    /// Cookies of the rule are set for the whole domain of the shop, subdomains included.
+    fn seed(&self, rule: &DomainRule) {
+        if rule.host.is_empty() || rule.cookies.is_empty() {
+            return;
+        }
+
+        let url = match url::Url::parse(&format!("https://{}/", rule.host)) {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+
+        let mut store = self.store.write().unwrap();
+        for (name, value) in &rule.cookies {
+            let cookie = format!("{}={}; Domain={}; Path=/", name, value, rule.host);
+            if let Err(error) = store.parse(&cookie, &url) {
+                log::warn!(
+                    "Cookie of the shop is not valid. Host: {}. Cookie: {}. Cause: {:?}",
+                    rule.host,
+                    name,
+                    error
+                );
+            }
+        }
+    }
+
+    /// Writes the cookies to the file, session cookies included,
+    /// as shops often keep the consent or region in them.
+    pub fn save(&self) -> error_stack::Result<(), CookieJarError> {
+        let path = match &self.file {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let mut json = Vec::new();
+        self.store
+            .read()
+            .unwrap()
+            .save_incl_expired_and_nonpersistent_json(&mut json)
+            .map_err(|error| {
+                error_stack::report!(CookieJarError)
+                    .attach_printable(format!("Couldn't serialize cookies. Cause: {:?}", error))
+            })?;
+
+        write_atomically(path, &json).map_err(|error| {
+            error_stack::report!(error)
+                .change_context(CookieJarError)
+                .attach_printable(format!("Couldn't save cookies. Path: {:?}", path))
+        })
+    }
+
+    /// Cookies the browser should send to the url. Expiry is left out,
+    /// as the jar keeps them between sessions anyway.
+    pub fn browser_cookies(&self, url: &url::Url) -> Vec<fantoccini::cookies::Cookie<'static>> {
+        self.store
+            .read()
+            .unwrap()
+            .matches(url)
+            .into_iter()
+            .map(|cookie| {
+                let mut browser_cookie = fantoccini::cookies::Cookie::new(
+                    cookie.name().to_owned(),
+                    cookie.value().to_owned(),
+                );
+                browser_cookie.set_path(String::from(&cookie.path));
+                if let CookieDomain::Suffix(domain) = &cookie.domain {
+                    browser_cookie.set_domain(domain.clone());
+                }
+                if cookie.secure() == Some(true) {
+                    browser_cookie.set_secure(true);
+                }
+                if cookie.http_only() == Some(true) {
+                    browser_cookie.set_http_only(true);
+                }
+                browser_cookie
+            })
+            .collect()
+    }
+
+    /// Keeps the cookies the browser had on the page of the url.
+    pub fn store_browser_cookies(
+        &self,
+        cookies: &[fantoccini::cookies::Cookie<'static>],
+        url: &url::Url,
+    ) {
+        let cookies = cookies
+            .iter()
+            .filter_map(|cookie| RawCookie::parse(cookie.to_string()).ok());
+        self.store
+            .write()
+            .unwrap()
+            .store_response_cookies(cookies, url);
+    }
+}
+
+impl reqwest::cookie::CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &url::Url) {
+        let cookies = cookie_headers
+            .filter_map(|header| std::str::from_utf8(header.as_bytes()).ok())
+            .filter_map(|header| RawCookie::parse(header.to_owned()).ok());
+        self.store
+            .write()
+            .unwrap()
+            .store_response_cookies(cookies, url);
+    }
+
+    fn cookies(&self, url: &url::Url) -> Option<HeaderValue> {
+        let header = self
+            .store
+            .read()
+            .unwrap()
+            .get_request_values(url)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        if header.is_empty() {
+            None
+        } else {
+            HeaderValue::from_str(&header).ok()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::cookie::CookieStore as _;
+
+    fn rule() -> DomainRule {
+        DomainRule {
+            host: "x-kom.pl".to_owned(),
+            cookies: [("region".to_owned(), "pl".to_owned())].into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn configured_cookies_are_sent_to_the_shop() {
+        let jar = CookieJar::load(None, &[rule()]);
+
+        let url = url::Url::parse("https://www.x-kom.pl/p/1").unwrap();
+        assert_eq!(
+            jar.cookies(&url),
+            Some(HeaderValue::from_static("region=pl"))
+        );
+
+        let other_shop = url::Url::parse("https://al.to/p/1").unwrap();
+        assert_eq!(jar.cookies(&other_shop), None);
+    }
+
+    #[test]
+    fn cookies_survive_between_runs() {
+        let dir = std::env::temp_dir().join(format!("cookies-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("cookies.json");
+        let url = url::Url::parse("https://x-kom.pl/p/1").unwrap();
+
+        let jar = CookieJar::load(Some(file.clone()), &[rule()]);
+        let set_cookie = HeaderValue::from_static("session=abc; Path=/");
+        jar.set_cookies(&mut std::iter::once(&set_cookie), &url);
+        jar.save().unwrap();
+
+        let jar = CookieJar::load(Some(file), &[]);
+        let header = jar.cookies(&url).unwrap();
+        let mut cookies: Vec<&str> = header.to_str().unwrap().split("; ").collect();
+        cookies.sort_unstable();
+        assert_eq!(cookies, ["region=pl", "session=abc"]);
+
+        let browser_cookies = jar.browser_cookies(&url);
+        assert_eq!(browser_cookies.len(), 2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
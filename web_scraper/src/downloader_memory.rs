@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::config::{DownloaderKind, DownloaderMemoryConfig};
+use crate::utils::write_atomically;
+
+#[derive(thiserror::Error, Debug)]
+#[error("Couldn't write the downloader memory")]
+pub struct DownloaderMemoryError;
+
+/// Shops which needed the browser, with the time they needed it the last time.
+/// Reqwest is tried again once the entry gets old.
+pub struct DownloaderMemory {
+    browser_needed: Mutex<HashMap<String, DateTime<Utc>>>,
+    recheck_after: Duration,
+    /// Where the memory is saved between runs
+    file: Option<PathBuf>,
+}
+
+impl DownloaderMemory {
+    pub fn load(config: &DownloaderMemoryConfig) -> Self {
+        let file = config.file.as_ref().map(PathBuf::from);
+        let browser_needed = match &file {
+            Some(path) if path.exists() => std::fs::read(path)
+                .map_err(|error| format!("{:?}", error))
+                .and_then(|json| {
+                    serde_json::from_slice(&json).map_err(|error| format!("{:?}", error))
+                })
+                .unwrap_or_else(|error| {
+                    log::warn!(
+                        "Couldn't load the downloader memory, starting without it. Path: {:?}. Cause: {}",
+                        path,
+                        error
+                    );
+                    HashMap::new()
+                }),
+            _ => HashMap::new(),
+        };
+
+        Self {
+            browser_needed: Mutex::new(browser_needed),
+            recheck_after: Duration::hours(config.recheck_after_hours as i64),
+            file,
+        }
+    }
+
+    /// `domain_key` as given by `DomainRule::domain_key`.
+    pub fn needs_browser(&self, domain_key: &str) -> bool {
+        self.browser_needed
+            .lock()
+            .unwrap()
+            .get(domain_key)
+            .is_some_and(|since| Utc::now() - *since < self.recheck_after)
+    }
+
+    /// Remembers the downloader which got the page of the shop.
+    /// The browser keeps the time it was needed first, so reqwest is tried again after
+    /// `recheck_after` however often the shop is downloaded with the browser meanwhile.
+    pub fn remember(&self, domain_key: &str, kind: DownloaderKind) {
+        let mut browser_needed = self.browser_needed.lock().unwrap();
+        match kind {
+            DownloaderKind::Fantoccini => {
+                browser_needed
+                    .entry(domain_key.to_owned())
+                    .or_insert_with(Utc::now);
+            }
+            DownloaderKind::Reqwest | DownloaderKind::Adaptive => {
+                browser_needed.remove(domain_key);
+            }
+        }
+    }
+
+    pub fn save(&self) -> error_stack::Result<(), DownloaderMemoryError> {
+        let path = match &self.file {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let json =
+            serde_json::to_vec_pretty(&*self.browser_needed.lock().unwrap()).map_err(|error| {
+                error_stack::report!(error)
+                    .change_context(DownloaderMemoryError)
+                    .attach_printable("Couldn't serialize the downloader memory")
+            })?;
+
+        write_atomically(path, &json).map_err(|error| {
+            error_stack::report!(error)
+                .change_context(DownloaderMemoryError)
+                .attach_printable(format!(
+                    "Couldn't save the downloader memory. Path: {:?}",
+                    path
+                ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn browser_is_remembered_between_runs() {
+        let dir = std::env::temp_dir().join(format!("downloaders-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = DownloaderMemoryConfig {
+            file: Some(dir.join("downloaders.json").to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+
+        let memory = DownloaderMemory::load(&config);
+        memory.remember("x-kom.pl", DownloaderKind::Fantoccini);
+        memory.remember("al.to", DownloaderKind::Fantoccini);
+        memory.remember("al.to", DownloaderKind::Reqwest);
+        memory.save().unwrap();
+
+        let memory = DownloaderMemory::load(&config);
+        assert!(memory.needs_browser("x-kom.pl"));
+        assert!(!memory.needs_browser("al.to"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reqwest_gets_another_chance() {
+        let memory = DownloaderMemory::load(&DownloaderMemoryConfig {
+            file: None,
+            recheck_after_hours: 24,
+        });
+        memory.remember("x-kom.pl", DownloaderKind::Fantoccini);
+        assert!(memory.needs_browser("x-kom.pl"));
+
+        memory
+            .browser_needed
+            .lock()
+            .unwrap()
+            .insert("x-kom.pl".to_owned(), Utc::now() - Duration::hours(25));
+        assert!(!memory.needs_browser("x-kom.pl"));
+    }
+
+    #[test]
+    fn using_the_browser_does_not_extend_the_recheck() {
+        let memory = DownloaderMemory::load(&DownloaderMemoryConfig {
+            file: None,
+            recheck_after_hours: 24,
+        });
+        memory
+            .browser_needed
+            .lock()
+            .unwrap()
+            .insert("x-kom.pl".to_owned(), Utc::now() - Duration::hours(25));
+
+        memory.remember("x-kom.pl", DownloaderKind::Fantoccini);
+        assert!(!memory.needs_browser("x-kom.pl"));
+    }
+}
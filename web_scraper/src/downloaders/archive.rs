@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+
+use super::DownloadedPage;
+
+/// One download saved in the archive. The html is kept apart, in `pages/<page_hash>.html`,
+/// so the same page downloaded many times is stored once.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ArchiveRecord {
+    pub requested_url: String,
+    /// Url of the page where the downloader landed
+    pub url: String,
+    pub status: Option<u16>,
+    pub headers: Vec<(String, String)>,
+    pub downloaded_at: chrono::NaiveDateTime,
+    /// Why scraping of the page failed, `None` when it went fine
+    pub scrape_error: Option<String>,
+    /// Sha256 of the html
+    pub page_hash: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Couldn't read or write the page archive")]
+pub struct ArchiveError;
+
+const RECORDS_FILE: &str = "records.jsonl";
+const PAGES_DIR: &str = "pages";
+
+/// Content addressed store of downloaded pages, so it can be seen later what the shop returned.
+pub struct PageArchive {
+    dir: PathBuf,
+}
+
+impl PageArchive {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub async fn save(
+        &self,
+        requested_url: &str,
+        page: &DownloadedPage,
+        scrape_error: Option<String>,
+    ) -> error_stack::Result<ArchiveRecord, ArchiveError> {
+        let page_hash = format!("{:x}", Sha256::digest(page.html.as_bytes()));
+
+        let pages_dir = self.dir.join(PAGES_DIR);
+        tokio::fs::create_dir_all(&pages_dir)
+            .await
+            .map_err(|error| {
+                error_stack::report!(error)
+                    .change_context(ArchiveError)
+                    .attach_printable(format!("Couldn't create directory. Path: {:?}", pages_dir))
+            })?;
+
+        let page_path = pages_dir.join(format!("{}.html", page_hash));
+        if !page_path.exists() {
+            tokio::fs::write(&page_path, &page.html)
+                .await
+                .map_err(|error| {
+                    error_stack::report!(error)
+                        .change_context(ArchiveError)
+                        .attach_printable(format!("Couldn't write page. Path: {:?}", page_path))
+                })?;
+        }
+
+        let record = ArchiveRecord {
+            requested_url: requested_url.to_owned(),
+            url: page.url.clone(),
+            status: page.status,
+            headers: page.headers.clone(),
+            downloaded_at: chrono::Utc::now().naive_utc(),
+            scrape_error,
+            page_hash,
+        };
+
+        let line = serde_json::to_string(&record).map_err(|error| {
+            error_stack::report!(error)
+                .change_context(ArchiveError)
+                .attach_printable("Couldn't serialize archive record")
+        })? + "\n";
+
+        // Whole line is written at once, so records of downloads running at the same time don't mix
+        let records_path = self.dir.join(RECORDS_FILE);
+        let mut records_file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&records_path)
+            .await
+            .map_err(|error| {
+                error_stack::report!(error)
+                    .change_context(ArchiveError)
+                    .attach_printable(format!("Couldn't open records. Path: {:?}", records_path))
+            })?;
+        records_file
+            .write_all(line.as_bytes())
+            .await
+            .and(records_file.flush().await)
+            .map_err(|error| {
+                error_stack::report!(error)
+                    .change_context(ArchiveError)
+                    .attach_printable(format!("Couldn't write record. Path: {:?}", records_path))
+            })?;
+
+        Ok(record)
+    }
+
+    /// Records in the order the pages were downloaded.
+    pub fn records(&self) -> error_stack::Result<Vec<ArchiveRecord>, ArchiveError> {
+        let records_path = self.dir.join(RECORDS_FILE);
+
+        let text = std::fs::read_to_string(&records_path).map_err(|error| {
+            error_stack::report!(error)
+                .change_context(ArchiveError)
+                .attach_printable(format!("Couldn't read records. Path: {:?}", records_path))
+        })?;
+
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line).map_err(|error| {
+                    error_stack::report!(error)
+                        .change_context(ArchiveError)
+                        .attach_printable(format!("Archive record is not valid. Record: {}", line))
+                })
+            })
+            .collect()
+    }
+
+    pub async fn read_page(
+        &self,
+        record: &ArchiveRecord,
+    ) -> error_stack::Result<String, ArchiveError> {
+        let page_path = self
+            .dir
+            .join(PAGES_DIR)
+            .join(format!("{}.html", record.page_hash));
+
+        tokio::fs::read_to_string(&page_path)
+            .await
+            .map_err(|error| {
+                error_stack::report!(error)
+                    .change_context(ArchiveError)
+                    .attach_printable(format!("Couldn't read page. Path: {:?}", page_path))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(html: &str) -> DownloadedPage {
+        DownloadedPage {
+            url: "https://shop.pl/p/1".to_owned(),
+            status: Some(200),
+            headers: vec![("content-type".to_owned(), "text/html".to_owned())],
+            html: html.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn same_page_is_stored_once() {
+        let dir = std::env::temp_dir().join(format!("page_archive_test_{}", std::process::id()));
+        let archive = PageArchive::new(&dir);
+
+        let first = archive
+            .save("https://shop.pl/p/1", &page("<p>1 zł</p>"), None)
+            .await
+            .unwrap();
+        let second = archive
+            .save(
+                "https://shop.pl/p/1",
+                &page("<p>1 zł</p>"),
+                Some("PriceNotFound".to_owned()),
+            )
+            .await
+            .unwrap();
+        archive
+            .save("https://shop.pl/p/2", &page("<p>2 zł</p>"), None)
+            .await
+            .unwrap();
+
+        assert_eq!(first.page_hash, second.page_hash);
+        assert_eq!(archive.records().unwrap().len(), 3);
+        assert_eq!(std::fs::read_dir(dir.join(PAGES_DIR)).unwrap().count(), 2);
+        assert_eq!(archive.read_page(&second).await.unwrap(), "<p>1 zł</p>");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,108 @@
+use crate::config::BotWallConfig;
+
+use super::{DownloadedPage, DownloadingError};
+
+/// Pages looking like a captcha or a bot wall are `Blocked` errors,
+/// with the page attached to the report like pages with an error status.
+/// Pages with an error status are rejected by `check_status` before they get here.
+pub fn check_bot_wall(
+    page: DownloadedPage,
+    config: &BotWallConfig,
+) -> error_stack::Result<DownloadedPage, DownloadingError> {
+    match find_fingerprint(&page.html, config) {
+        Some(fingerprint) => Err(error_stack::report!(DownloadingError::Blocked)
+            .attach_printable(format!(
+                "Page looks like a bot wall. Found: {}",
+                fingerprint
+            ))
+            .attach_printable(format!("Url: {}", page.url))
+            .attach(page)),
+        None => Ok(page),
+    }
+}
+
+/// Runs on every downloaded page, so the html is searched as it is, without parsing or copying it.
+fn find_fingerprint<'a>(html: &str, config: &'a BotWallConfig) -> Option<&'a str> {
+    let marker = config
+        .markers
+        .iter()
+        .find(|marker| find_ignore_case(html, marker).is_some());
+    if let Some(marker) = marker {
+        return Some(marker);
+    }
+
+    let title = page_title(html)?.to_lowercase();
+    config
+        .title_patterns
+        .iter()
+        .find(|pattern| title.contains(&pattern.to_lowercase()))
+        .map(String::as_str)
+}
+
+/// Text of the first `<title>` element.
+fn page_title(html: &str) -> Option<&str> {
+    let start = find_ignore_case(html, "<title")?;
+    let start = start + html[start..].find('>')? + 1;
+    let end = start + find_ignore_case(&html[start..], "</title")?;
+    Some(&html[start..end])
+}
+
+/// Byte index of the needle, ascii letters compared case insensitively.
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(html: &str) -> DownloadedPage {
+        DownloadedPage {
+            url: "https://shop.pl/p/1".to_owned(),
+            status: Some(200),
+            headers: Vec::new(),
+            html: html.to_owned(),
+        }
+    }
+
+    #[test]
+    fn challenge_pages_are_blocked() {
+        let config = BotWallConfig::default();
+
+        let cloudflare = "<html><head><title>Just a moment...</title></head>\
+            <body><script>window._cf_chl_opt={cType: 'managed'};</script></body></html>";
+        let error = check_bot_wall(page(cloudflare), &config).unwrap_err();
+        assert_eq!(error.current_context(), &DownloadingError::Blocked);
+        assert!(error.downcast_ref::<DownloadedPage>().is_some());
+
+        let datadome = "<html><head><title>x-kom.pl</title></head>\
+            <body><iframe src=\"https://geo.captcha-delivery.com/captcha/?initialCid=1\"></iframe></body></html>";
+        assert!(check_bot_wall(page(datadome), &config).is_err());
+    }
+
+    #[test]
+    fn product_pages_pass() {
+        let html = "<html><head><title>Karta graficzna RTX 3060 - Sklep</title></head>\
+            <body><p>Just a moment of your time: subscribe!</p><span class=\"price\">1 299,00 zł</span></body></html>";
+        assert!(check_bot_wall(page(html), &BotWallConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn title_is_found_without_parsing() {
+        assert_eq!(
+            page_title("<HTML><Title lang=\"pl\">Sklep</TITLE></HTML>"),
+            Some("Sklep")
+        );
+        assert_eq!(page_title("<html><body>Sklep</body></html>"), None);
+        assert_eq!(
+            find_ignore_case("Źródło CF_CHL_OPT", "cf_chl_opt"),
+            Some(10)
+        );
+    }
+}
@@ -1,8 +1,12 @@
-use fantoccini::ClientBuilder;
+use fantoccini::Locator;
+use std::time::Duration;
 
+use crate::config::{default_step_timeout_ms, BrowserStep, DomainRule, ProfileRotation};
+use crate::cookies::CookieJar;
 use crate::price_scraper::PriceScraper;
+use serde_json::json;
 
-use super::{get_url_struct, Downloader, DownloadingError};
+use super::{get_url_struct, DownloadedPage, Downloader, DownloadingError};
 
 pub struct FantocciniDownloader;
 
@@ -10,9 +14,10 @@ pub struct FantocciniDownloader;
 impl Downloader for FantocciniDownloader {
     async fn download_page(
         &self,
-        _price_scraper: &PriceScraper,
+        price_scraper: &PriceScraper,
+        rule: &DomainRule,
         url: &str,
-    ) -> error_stack::Result<String, DownloadingError> {
+    ) -> error_stack::Result<DownloadedPage, DownloadingError> {
         // Check if url is valid
         let url_struct = get_url_struct(url).map_err(|error| {
             error_stack::report!(error)
@@ -20,115 +25,253 @@ impl Downloader for FantocciniDownloader {
                 .attach_printable(format!("Given url is not valid. Url: {}", url))
         })?;
 
-        // Create fantoccini client
-        let fantoccini_client = create_fantoccini_client().await.map_err(|error| {
-            error_stack::report!(error)
-                .change_context(DownloadingError::CreatingDownloaderClient)
-                .attach_printable("Couldn't create fantoccini client")
-        })?;
+        // Borrow a browser session. Its profile can't change, so rotating per request
+        // takes whichever session is free, as each one got a random profile
+        let profiles = &price_scraper.profiles;
+        let profile = match profiles.rotation() {
+            ProfileRotation::PerRequest => None,
+            ProfileRotation::PerDomain => Some(profiles.choose(&rule.domain_key(url))),
+        };
 
-        // Go to the page
-        fantoccini_client.goto(url).await.map_err(|error| {
-            error_stack::report!(error)
-                .change_context(DownloadingError::Other)
-                .attach_printable(format!(
-                    "Couldn't go to the given url with the fantoccini client. Url: {}",
-                    url
-                ))
-        })?;
+        let proxies = &price_scraper.proxies;
+        let proxy = proxies.route(rule, url);
 
-        // Get url of the page where we landed
-        let downloaded_url = fantoccini_client
-            .current_url()
+        let mut session = price_scraper
+            .webdriver_pool
+            .acquire(profiles, profile, proxies, proxy)
             .await
             .map_err(|error| {
-                error_stack::report!(error)
-                    .change_context(DownloadingError::CannotGetDownloadedUrl)
-                    .attach_printable(format!("Couldn't get url from the response of fantoccini client. Requested url was: {}", url))
+                error
+                    .change_context(DownloadingError::CreatingDownloaderClient)
+                    .attach_printable("Couldn't get a WebDriver session")
             })?;
 
-        // Handle redirection case
-        if url_struct != downloaded_url {
-            return Err(error_stack::report!(DownloadingError::Redirection)
-                .attach_printable("Downloaded page comes from different url than requested.")
-                .attach_printable(format!("Requested url: {}", url))
-                .attach_printable(format!("Downloaded url: {}", downloaded_url)));
+        let page = download_with_client(
+            session.client(),
+            &price_scraper.cookies,
+            rule,
+            url,
+            &url_struct,
+        )
+        .await;
+        if let Some(proxy) = proxy {
+            proxies.report(proxy, rule, url, &page);
         }
 
-        // Get html source
-        let document = fantoccini_client.source().await.map_err(|error| {
-            error_stack::report!(error)
-                .change_context(DownloadingError::GetSourceFromResponse)
-                .attach_printable(format!(
-                    "Couldn't get the source html code from response of the given url. Url: {}",
-                    url
-                ))
+        // Browser might be left in any state after an error
+        if page.is_err() {
+            session.discard();
+        }
+        session.release().await;
+
+        page
+    }
+}
+
+async fn download_with_client(
+    fantoccini_client: &fantoccini::Client,
+    cookies: &CookieJar,
+    rule: &DomainRule,
+    url: &str,
+    url_struct: &url::Url,
+) -> error_stack::Result<DownloadedPage, DownloadingError> {
+    set_browser_cookies(fantoccini_client, cookies, url_struct).await;
+
+    // Go to the page
+    fantoccini_client.goto(url).await.map_err(|error| {
+        error_stack::report!(error)
+            .change_context(DownloadingError::Other)
+            .attach_printable(format!(
+                "Couldn't go to the given url with the fantoccini client. Url: {}",
+                url
+            ))
+    })?;
+
+    // Get url of the page where we landed. Redirects are judged by the scraper,
+    // it knows if the page still shows the product
+    let downloaded_url = fantoccini_client.current_url().await.map_err(|error| {
+        error_stack::report!(error)
+            .change_context(DownloadingError::CannotGetDownloadedUrl)
+            .attach_printable(format!(
+                "Couldn't get url from the response of fantoccini client. Requested url was: {}",
+                url
+            ))
+    })?;
+
+    // Wait for the content rendered by javascript
+    if let Some(css_selector) = &rule.wait_for_selector {
+        let timeout_ms = rule
+            .wait_for_selector_timeout_ms
+            .unwrap_or_else(default_step_timeout_ms);
+        wait_for(fantoccini_client, css_selector, timeout_ms)
+            .await
+            .map_err(|error| error.attach_printable(format!("Url: {}", url)))?;
+    }
+
+    for step in &rule.browser_steps {
+        run_step(fantoccini_client, step).await.map_err(|error| {
+            error
+                .attach_printable(format!("Browser step: {:?}", step))
+                .attach_printable(format!("Url: {}", url))
         })?;
+    }
 
-        Ok(document)
+    // Get html source
+    let html = fantoccini_client.source().await.map_err(|error| {
+        error_stack::report!(error)
+            .change_context(DownloadingError::GetSourceFromResponse)
+            .attach_printable(format!(
+                "Couldn't get the source html code from response of the given url. Url: {}",
+                url
+            ))
+    })?;
+
+    // Keep cookies the shop set, e.g. after accepting the consent in browser steps
+    match fantoccini_client.get_all_cookies().await {
+        Ok(browser_cookies) => cookies.store_browser_cookies(&browser_cookies, &downloaded_url),
+        Err(error) => log::warn!(
+            "Couldn't get cookies from the browser. Url: {}. Cause: {:?}",
+            url,
+            error
+        ),
     }
+
+    Ok(DownloadedPage {
+        url: downloaded_url.to_string(),
+        status: None,
+        headers: Vec::new(),
+        html,
+    })
 }
 
-#[derive(thiserror::Error, Debug)]
-#[error("Couldn't create fantoccini client")]
-enum CreateFantocciniError {
-    BadWebdriverUrl,
-    ConnectingToWebdriver,
-    SettingUserAgent,
+async fn run_step(
+    fantoccini_client: &fantoccini::Client,
+    step: &BrowserStep,
+) -> error_stack::Result<(), DownloadingError> {
+    match step {
+        BrowserStep::WaitFor {
+            selector,
+            timeout_ms,
+        } => {
+            wait_for(fantoccini_client, selector, *timeout_ms).await?;
+        }
+        BrowserStep::Click {
+            selector,
+            timeout_ms,
+            optional,
+        } => {
+            let element = match wait_for(fantoccini_client, selector, *timeout_ms).await {
+                Ok(v) => v,
+                Err(_) if *optional => return Ok(()),
+                Err(error) => return Err(error),
+            };
+            element.click().await.map_err(|error| {
+                error_stack::report!(error)
+                    .change_context(DownloadingError::BrowserStep)
+                    .attach_printable(format!("Couldn't click. Css selector: {}", selector))
+            })?;
+        }
+        BrowserStep::Select { selector, value } => {
+            let element = fantoccini_client
+                .find(Locator::Css(selector))
+                .await
+                .map_err(|error| {
+                    error_stack::report!(error)
+                        .change_context(DownloadingError::BrowserStep)
+                        .attach_printable(format!("Element not found. Css selector: {}", selector))
+                })?;
+            element.select_by_value(value).await.map_err(|error| {
+                error_stack::report!(error)
+                    .change_context(DownloadingError::BrowserStep)
+                    .attach_printable(format!(
+                        "Couldn't select the option. Css selector: {}. Value: {}",
+                        selector, value
+                    ))
+            })?;
+        }
+        BrowserStep::Scroll { selector, pixels } => {
+            let (script, argument) = match selector {
+                Some(selector) => (
+                    "document.querySelector(arguments[0]).scrollIntoView()",
+                    json!(selector),
+                ),
+                None => ("window.scrollBy(0, arguments[0])", json!(pixels)),
+            };
+            fantoccini_client
+                .execute(script, vec![argument])
+                .await
+                .map_err(|error| {
+                    error_stack::report!(error)
+                        .change_context(DownloadingError::BrowserStep)
+                        .attach_printable("Couldn't scroll")
+                })?;
+        }
+        BrowserStep::Sleep { ms } => tokio::time::sleep(Duration::from_millis(*ms)).await,
+    }
+
+    Ok(())
 }
 
-async fn create_fantoccini_client() -> error_stack::Result<fantoccini::Client, CreateFantocciniError>
-{
-    // TODO: Get url for webdriver from config
-    let webdriver_url = "http://localhost:4444";
+/// Gives the browser cookies of the jar for the url. Browsers accept cookies only
+/// on a page of their domain, robots.txt is opened first when the session is somewhere else.
+/// Failures are only logged, the page might be fine without the cookies.
+async fn set_browser_cookies(
+    fantoccini_client: &fantoccini::Client,
+    cookies: &CookieJar,
+    url_struct: &url::Url,
+) {
+    let browser_cookies = cookies.browser_cookies(url_struct);
+    if browser_cookies.is_empty() {
+        return;
+    }
 
-    // Connecting to webdriver
-    let fantoccini_client = ClientBuilder::native()
-        .connect(webdriver_url)
+    let same_origin = fantoccini_client
+        .current_url()
         .await
-        .map_err(|error| {
-            match error {
-                fantoccini::error::NewSessionError::BadWebdriverUrl(error) => {
-                    error_stack::report!(error)
-                    .change_context(CreateFantocciniError::BadWebdriverUrl)
-                    .attach_printable(
-                        format!(
-                            "Url provided for connecting to selenium webdriver for fantoccini is wrong. 
-                            Please check config file and if docker selenium webdriver is working properly
-                            Provided webdriver url: {}", webdriver_url
-                        )
-                    )
-                },
-                error => {
-                    error_stack::report!(error)
-                    .change_context(CreateFantocciniError::ConnectingToWebdriver)
-                    .attach_printable(
-                        format!(
-                            "Error occured while connecting to selenium webdriver. 
-                            Please check config file and if docker selenium webdriver is working properly. 
-                            Maybe webdriver is overloaded or misconfigured.
-                            Maybe it needs restarting.
-                            Provided webdriver url: {}", webdriver_url
-                        )
-                    )
-                }
-            }
-        })?;
+        .is_ok_and(|current_url| current_url.origin() == url_struct.origin());
+    if !same_origin {
+        let robots_url = url_struct
+            .join("/robots.txt")
+            .unwrap_or_else(|_| url_struct.clone());
+        if let Err(error) = fantoccini_client.goto(robots_url.as_str()).await {
+            log::warn!(
+                "Couldn't open the shop to set its cookies. Url: {}. Cause: {:?}",
+                robots_url,
+                error
+            );
+            return;
+        }
+    }
 
-    // Set the user-agent
-    // TODO: Get random user-agent from some list in the config
-    let user_agent = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/103.0.0.0 Safari/537.36";
+    for cookie in browser_cookies {
+        let name = cookie.name().to_owned();
+        if let Err(error) = fantoccini_client.add_cookie(cookie).await {
+            log::warn!(
+                "Browser didn't accept the cookie. Cookie: {}. Url: {}. Cause: {:?}",
+                name,
+                url_struct,
+                error
+            );
+        }
+    }
+}
+
+async fn wait_for(
+    fantoccini_client: &fantoccini::Client,
+    css_selector: &str,
+    timeout_ms: u64,
+) -> error_stack::Result<fantoccini::elements::Element, DownloadingError> {
     fantoccini_client
-        .set_ua(user_agent)
+        .wait()
+        .at_most(Duration::from_millis(timeout_ms))
+        .for_element(Locator::Css(css_selector))
         .await
         .map_err(|error| {
             error_stack::report!(error)
-                .change_context(CreateFantocciniError::SettingUserAgent)
+                .change_context(DownloadingError::Timeout)
                 .attach_printable(format!(
-                    "Couldn't set user agent for some unknown reason. Tried to set user agent: {}",
-                    user_agent
+                    "Element didn't appear on the page. Css selector: {}",
+                    css_selector
                 ))
-        })?;
-
-    Ok(fantoccini_client)
+        })
 }
@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::DomainRule;
+use crate::price_scraper::PriceScraper;
+
+use super::{DownloadedPage, Downloader, DownloadingError};
+
+/// Saved page of a shop with what the scraper is expected to find on it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Fixture {
+    pub url: String,
+    /// File name, relative to the directory of the manifest
+    pub page: String,
+    pub price: Option<String>,
+    pub currency: Option<String>,
+    pub availability: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Couldn't load or save fixtures")]
+pub struct FixturesError;
+
+pub const MANIFEST_FILE: &str = "fixtures.json";
+
+/// Serves pages saved on disk instead of downloading them, so scraping can be tested offline.
+pub struct FixtureDownloader {
+    pages: HashMap<String, PathBuf>,
+}
+
+impl FixtureDownloader {
+    /// Serves pages listed in the `fixtures.json` manifest of the given directory.
+    pub fn from_dir(dir: &Path) -> error_stack::Result<Self, FixturesError> {
+        let pages = load_manifest(dir)?
+            .into_iter()
+            .map(|fixture| (fixture.url, dir.join(fixture.page)))
+            .collect();
+
+        Ok(Self { pages })
+    }
+}
+
+#[async_trait::async_trait]
+impl Downloader for FixtureDownloader {
+    async fn download_page(
+        &self,
+        _price_scraper: &PriceScraper,
+        _rule: &DomainRule,
+        url: &str,
+    ) -> error_stack::Result<DownloadedPage, DownloadingError> {
+        let path = self.pages.get(url).ok_or_else(|| {
+            error_stack::report!(DownloadingError::Other)
+                .attach_printable(format!("There's no fixture for the url. Url: {}", url))
+        })?;
+
+        let html = tokio::fs::read_to_string(path).await.map_err(|error| {
+            error_stack::report!(error)
+                .change_context(DownloadingError::GetSourceFromResponse)
+                .attach_printable(format!("Couldn't read fixture. Path: {:?}", path))
+        })?;
+
+        Ok(DownloadedPage {
+            url: url.to_owned(),
+            status: None,
+            headers: Vec::new(),
+            html,
+        })
+    }
+}
+
+pub fn load_manifest(dir: &Path) -> error_stack::Result<Vec<Fixture>, FixturesError> {
+    let path = dir.join(MANIFEST_FILE);
+
+    let text = std::fs::read_to_string(&path).map_err(|error| {
+        error_stack::report!(error)
+            .change_context(FixturesError)
+            .attach_printable(format!("Couldn't read manifest. Path: {:?}", path))
+    })?;
+
+    serde_json::from_str(&text).map_err(|error| {
+        error_stack::report!(error)
+            .change_context(FixturesError)
+            .attach_printable(format!("Manifest is not valid. Path: {:?}", path))
+    })
+}
+
+pub fn save_manifest(dir: &Path, fixtures: &[Fixture]) -> error_stack::Result<(), FixturesError> {
+    let path = dir.join(MANIFEST_FILE);
+
+    let text = serde_json::to_string_pretty(fixtures).map_err(|error| {
+        error_stack::report!(error)
+            .change_context(FixturesError)
+            .attach_printable("Couldn't serialize manifest")
+    })?;
+
+    std::fs::write(&path, text + "\n").map_err(|error| {
+        error_stack::report!(error)
+            .change_context(FixturesError)
+            .attach_printable(format!("Couldn't write manifest. Path: {:?}", path))
+    })
+}
+
+/// E.g. "https://www.x-kom.pl/p/123-gpu.html" gives "www.x-kom.pl_p_123-gpu.html.html"
+pub fn page_file_name(url: &url::Url) -> String {
+    let name: String = format!("{}{}", url.host_str().unwrap_or_default(), url.path())
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    format!("{}.html", name.trim_end_matches('_'))
+}
@@ -1,9 +1,15 @@
+use crate::config::DomainRule;
 use crate::price_scraper::PriceScraper;
 
+pub mod archive;
+pub mod bot_wall;
 pub mod fantoccini;
+pub mod fixture;
+pub mod replay;
 pub mod reqwest;
+pub mod webdriver_pool;
 
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
 #[error("Couldn't download page")]
 pub enum DownloadingError {
     Timeout,
@@ -12,18 +18,156 @@ pub enum DownloadingError {
     GetSourceFromResponse,
     CannotGetDownloadedUrl,
     NotValidInputUrl,
+    DisallowedByRobots,
+    BrowserStep,
+    /// 404 or 410
+    NotFound,
+    /// 401 or 403, e.g. a captcha or a bot wall
+    Blocked,
+    /// 5xx, e.g. maintenance of the shop
+    ServerError,
+    /// 429, a `RetryAfter` is attached when the shop said how long to wait
+    RateLimited,
     Other,
 }
 
+/// How long the shop asked to wait before the next request, from the Retry-After header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryAfter(pub std::time::Duration);
+
+/// Page as it was received, with what's needed to tell later why scraping it failed.
+#[derive(Debug, Clone)]
+pub struct DownloadedPage {
+    /// Url of the page where the downloader landed
+    pub url: String,
+    /// Browser downloaders don't know it
+    pub status: Option<u16>,
+    pub headers: Vec<(String, String)>,
+    pub html: String,
+}
+
 #[async_trait::async_trait]
 pub trait Downloader {
     async fn download_page(
         &self,
         price_scraper: &PriceScraper,
+        rule: &DomainRule,
         url: &str,
-    ) -> error_stack::Result<String, DownloadingError>;
+    ) -> error_stack::Result<DownloadedPage, DownloadingError>;
+}
+
+/// Pages with an error status are errors, the page is attached to the report
+/// so it can be archived, e.g. to see what the captcha looked like.
+pub fn check_status(page: DownloadedPage) -> error_stack::Result<DownloadedPage, DownloadingError> {
+    let status = match page.status {
+        Some(v) => v,
+        None => return Ok(page),
+    };
+
+    let error = match status {
+        404 | 410 => DownloadingError::NotFound,
+        401 | 403 => DownloadingError::Blocked,
+        429 => DownloadingError::RateLimited,
+        500..=599 => DownloadingError::ServerError,
+        _ => return Ok(page),
+    };
+
+    let retry_after = page
+        .headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("retry-after"))
+        .and_then(|(_, value)| parse_retry_after(value, chrono::Utc::now()));
+
+    let mut report = error_stack::report!(error)
+        .attach_printable(format!("Shop responded with status {}", status))
+        .attach_printable(format!("Url: {}", page.url));
+    if let Some(retry_after) = retry_after {
+        report = report
+            .attach_printable(format!("Shop asked to wait {:?}", retry_after))
+            .attach(RetryAfter(retry_after));
+    }
+    Err(report.attach(page))
+}
+
+/// Retry-After is either a number of seconds or a date.
+fn parse_retry_after(
+    value: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Option<std::time::Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(std::time::Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&chrono::Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
 }
 
 fn get_url_struct(url: &str) -> Result<url::Url, url::ParseError> {
     url::Url::parse(url)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(status: u16, headers: &[(&str, &str)]) -> DownloadedPage {
+        DownloadedPage {
+            url: "https://shop.pl/p/1".to_owned(),
+            status: Some(status),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            html: String::new(),
+        }
+    }
+
+    #[test]
+    fn statuses_are_classified() {
+        assert!(check_status(page(200, &[])).is_ok());
+        assert!(check_status(page(301, &[])).is_ok());
+
+        let error = check_status(page(404, &[])).unwrap_err();
+        assert_eq!(error.current_context(), &DownloadingError::NotFound);
+        assert_eq!(
+            error.downcast_ref::<DownloadedPage>().unwrap().status,
+            Some(404)
+        );
+
+        let error = check_status(page(403, &[])).unwrap_err();
+        assert_eq!(error.current_context(), &DownloadingError::Blocked);
+
+        let error = check_status(page(503, &[])).unwrap_err();
+        assert_eq!(error.current_context(), &DownloadingError::ServerError);
+        assert!(error.downcast_ref::<RetryAfter>().is_none());
+
+        let error = check_status(page(429, &[("Retry-After", "120")])).unwrap_err();
+        assert_eq!(error.current_context(), &DownloadingError::RateLimited);
+        assert_eq!(
+            error.downcast_ref::<RetryAfter>(),
+            Some(&RetryAfter(std::time::Duration::from_secs(120)))
+        );
+    }
+
+    #[test]
+    fn retry_after_date() {
+        let now = chrono::DateTime::parse_from_rfc2822("Wed, 21 Oct 2026 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&chrono::Utc);
+
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2026 07:29:30 GMT", now),
+            Some(std::time::Duration::from_secs(90))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2026 07:00:00 GMT", now),
+            Some(std::time::Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use crate::config::DomainRule;
+use crate::price_scraper::PriceScraper;
+
+use super::archive::{ArchiveError, ArchiveRecord, PageArchive};
+use super::{DownloadedPage, Downloader, DownloadingError};
+
+/// Serves pages from the archive instead of downloading them, so fixes of selectors
+/// can be checked against exactly what the shop returned.
+pub struct ReplayDownloader {
+    archive: PageArchive,
+    /// The latest record of every requested url
+    records: HashMap<String, ArchiveRecord>,
+}
+
+impl ReplayDownloader {
+    pub fn new(archive: PageArchive) -> error_stack::Result<Self, ArchiveError> {
+        let records = archive
+            .records()?
+            .into_iter()
+            .map(|record| (record.requested_url.clone(), record))
+            .collect();
+
+        Ok(Self { archive, records })
+    }
+
+    /// Urls for which there's an archived page.
+    pub fn urls(&self) -> impl Iterator<Item = &str> {
+        self.records.keys().map(String::as_str)
+    }
+
+    pub fn record(&self, url: &str) -> Option<&ArchiveRecord> {
+        self.records.get(url)
+    }
+}
+
+#[async_trait::async_trait]
+impl Downloader for ReplayDownloader {
+    async fn download_page(
+        &self,
+        _price_scraper: &PriceScraper,
+        _rule: &DomainRule,
+        url: &str,
+    ) -> error_stack::Result<DownloadedPage, DownloadingError> {
+        let record = self.records.get(url).ok_or_else(|| {
+            error_stack::report!(DownloadingError::Other).attach_printable(format!(
+                "There's no archived page for the url. Url: {}",
+                url
+            ))
+        })?;
+
+        let html = self
+            .archive
+            .read_page(record)
+            .await
+            .map_err(|error| error.change_context(DownloadingError::GetSourceFromResponse))?;
+
+        Ok(DownloadedPage {
+            url: record.url.clone(),
+            status: record.status,
+            headers: record.headers.clone(),
+            html,
+        })
+    }
+}
@@ -1,4 +1,7 @@
-use super::{get_url_struct, Downloader, DownloadingError};
+use std::collections::HashMap;
+
+use super::{get_url_struct, DownloadedPage, Downloader, DownloadingError};
+use crate::config::DomainRule;
 use crate::price_scraper::PriceScraper;
 
 pub struct ReqwestDownloader;
@@ -8,52 +11,79 @@ impl Downloader for ReqwestDownloader {
     async fn download_page(
         &self,
         price_scraper: &PriceScraper,
+        rule: &DomainRule,
         url: &str,
-    ) -> error_stack::Result<String, DownloadingError> {
+    ) -> error_stack::Result<DownloadedPage, DownloadingError> {
         // Check if url is valid
-        let url_struct = get_url_struct(url.trim_end_matches(|c| c == '/')).map_err(|error| {
+        get_url_struct(url.trim_end_matches(|c| c == '/')).map_err(|error| {
             error_stack::report!(error)
                 .change_context(DownloadingError::NotValidInputUrl)
                 .attach_printable("Given url is not valid")
                 .attach_printable(format!("Requested url: {}", url))
         })?;
 
-        // Send request and get a response
-        let response = price_scraper
-            .reqwest_client
-            .get(url)
-            .send()
-            .await
-            .map_err(|error| {
-                if error.is_timeout() {
-                    error_stack::report!(error)
-                        .change_context(DownloadingError::Timeout)
-                        .attach_printable("Cannot download page due to timeout")
-                        .attach_printable(format!("Requested url: {}", url))
-                } else if error.is_builder() {
-                    error_stack::report!(error)
-                        .change_context(DownloadingError::CreatingDownloaderClient)
-                        .attach_printable(
-                            "Cannot download page cause of error building reqwest client",
-                        )
-                        .attach_printable(format!("Requested url: {}", url))
-                } else if error.is_redirect() {
-                    error_stack::report!(error)
-                        .change_context(DownloadingError::Redirection)
-                        .attach_printable("Cannot download page cause of redirection error")
-                        .attach_printable(format!("Requested url: {}", url))
-                } else {
-                    error_stack::report!(error)
-                        .change_context(DownloadingError::Other)
-                        .attach_printable("Cannot download page cause of unknown error")
-                        .attach_printable(format!("Requested url: {}", url))
-                }
-            })?;
+        // Build request with headers of the profile, overridden by the ones of the shop
+        let profiles = &price_scraper.profiles;
+        let profile = profiles.get(profiles.choose(&rule.domain_key(url)));
+
+        let mut headers: HashMap<String, &str> = HashMap::new();
+        headers.insert("user-agent".to_owned(), &profile.user_agent);
+        for (name, value) in profile.headers.iter().chain(&rule.headers) {
+            headers.insert(name.to_lowercase(), value);
+        }
+
+        let proxies = &price_scraper.proxies;
+        let proxy = proxies.route(rule, url);
+        let client = match proxy {
+            Some(proxy) => proxies.client(proxy),
+            None => &price_scraper.reqwest_client,
+        };
+
+        let request = headers
+            .iter()
+            .fold(client.get(url), |request, (k, v)| request.header(k, *v));
+
+        let page = download(request, url).await;
+        if let Some(proxy) = proxy {
+            proxies.report(proxy, rule, url, &page);
+        }
+
+        page
+    }
+}
+
+async fn download(
+    request: reqwest::RequestBuilder,
+    url: &str,
+) -> error_stack::Result<DownloadedPage, DownloadingError> {
+    // Send request and get a response
+    let response = request.send().await.map_err(|error| {
+        if error.is_timeout() {
+            error_stack::report!(error)
+                .change_context(DownloadingError::Timeout)
+                .attach_printable("Cannot download page due to timeout")
+                .attach_printable(format!("Requested url: {}", url))
+        } else if error.is_builder() {
+            error_stack::report!(error)
+                .change_context(DownloadingError::CreatingDownloaderClient)
+                .attach_printable("Cannot download page cause of error building reqwest client")
+                .attach_printable(format!("Requested url: {}", url))
+        } else if error.is_redirect() {
+            error_stack::report!(error)
+                .change_context(DownloadingError::Redirection)
+                .attach_printable("Cannot download page cause of redirection error")
+                .attach_printable(format!("Requested url: {}", url))
+        } else {
+            error_stack::report!(error)
+                .change_context(DownloadingError::Other)
+                .attach_printable("Cannot download page cause of unknown error")
+                .attach_printable(format!("Requested url: {}", url))
+        }
+    })?;
 
-        // Get url of the downloaded page
-        let downloaded_url = url::Url::parse(
-            response.url().to_string().trim_end_matches(|c| c == '/'),
-        )
+    // Get url of the downloaded page. Redirects are judged by the scraper,
+    // it knows if the page still shows the product
+    let downloaded_url = url::Url::parse(response.url().to_string().trim_end_matches(|c| c == '/'))
         .map_err(|error| {
             error_stack::report!(error)
                 .change_context(DownloadingError::CannotGetDownloadedUrl)
@@ -61,22 +91,30 @@ impl Downloader for ReqwestDownloader {
                 .attach_printable(format!("Url tried to parse: {}", response.url()))
         })?;
 
-        // Handle redirection case
-        if url_struct != downloaded_url {
-            return Err(error_stack::report!(DownloadingError::Redirection)
-                .attach_printable("Downloaded page comes from different url than requested")
-                .attach_printable(format!("Requested url: {}", url))
-                .attach_printable(format!("Downloaded url: {}", downloaded_url)));
-        }
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(k, v)| {
+            (
+                k.to_string(),
+                String::from_utf8_lossy(v.as_bytes()).into_owned(),
+            )
+        })
+        .collect();
 
-        // Get the source html
-        let text = response.text().await.map_err(|error| {
-            error_stack::report!(error)
-                .change_context(DownloadingError::GetSourceFromResponse)
-                .attach_printable("Couldn't retrieve text from downloaded page for unknown reason")
-                .attach_printable(format!("Requested url: {}", url))
-        })?;
+    // Get the source html
+    let html = response.text().await.map_err(|error| {
+        error_stack::report!(error)
+            .change_context(DownloadingError::GetSourceFromResponse)
+            .attach_printable("Couldn't retrieve text from downloaded page for unknown reason")
+            .attach_printable(format!("Requested url: {}", url))
+    })?;
 
-        Ok(text)
-    }
+    Ok(DownloadedPage {
+        url: downloaded_url.to_string(),
+        status: Some(status),
+        headers,
+        html,
+    })
 }
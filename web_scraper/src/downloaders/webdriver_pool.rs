@@ -0,0 +1,427 @@
+use fantoccini::wd::Capabilities;
+use fantoccini::ClientBuilder;
+use serde_json::json;
+use std::sync::Mutex;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::config::{Browser, BrowserProfile, WebDriverConfig};
+use crate::profiles::ProfilePool;
+use crate::proxies::ProxyPool;
+
+#[derive(thiserror::Error, Debug)]
+#[error("Couldn't create fantoccini client")]
+pub enum CreateFantocciniError {
+    BadWebdriverUrl,
+    ConnectingToWebdriver,
+}
+
+struct Session {
+    client: fantoccini::Client,
+    /// Pages downloaded with the session so far
+    pages: u32,
+    /// The browser presents itself with this profile of the pool until it's closed
+    profile: usize,
+    /// And connects through this proxy, or directly
+    proxy: Option<usize>,
+}
+
+/// Keeps WebDriver sessions open between pages, as creating one takes seconds
+/// and the webdriver accepts only a few of them at once.
+pub struct SessionPool {
+    config: WebDriverConfig,
+    /// One permit for every session that may exist
+    permits: Semaphore,
+    idle: Mutex<Vec<Session>>,
+}
+
+/// Session borrowed from the pool. Give it back with `release`,
+/// otherwise it's closed in the background when dropped.
+pub struct PooledSession<'a> {
+    pool: &'a SessionPool,
+    session: Option<Session>,
+    broken: bool,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl SessionPool {
+    pub fn new(config: WebDriverConfig) -> Self {
+        Self {
+            permits: Semaphore::new(config.pool_size.max(1)),
+            idle: Mutex::new(Vec::new()),
+            config,
+        }
+    }
+
+    /// Waits for a free session with the given profile and proxy, any profile will do when it's `None`.
+    /// Idle sessions are checked if they still work, a new one is created when there's none.
+    pub async fn acquire(
+        &self,
+        profiles: &ProfilePool,
+        profile: Option<usize>,
+        proxies: &ProxyPool,
+        proxy: Option<usize>,
+    ) -> error_stack::Result<PooledSession<'_>, CreateFantocciniError> {
+        let permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("Semaphore of the session pool is never closed");
+
+        loop {
+            let (matching, other) = {
+                let mut idle = self.idle.lock().unwrap();
+                let position = idle.iter().rposition(|session| {
+                    profile.is_none_or(|profile| profile == session.profile)
+                        && proxy == session.proxy
+                });
+                match position {
+                    Some(position) => (Some(idle.remove(position)), None),
+                    // There can't be more sessions than permits, the oldest one makes room for the new one
+                    None if !idle.is_empty() => (None, Some(idle.remove(0))),
+                    None => (None, None),
+                }
+            };
+
+            if let Some(other) = other {
+                close_session(other).await;
+            }
+            let session = match matching {
+                Some(v) => v,
+                None => break,
+            };
+
+            if session.client.current_url().await.is_ok() {
+                return Ok(PooledSession {
+                    pool: self,
+                    session: Some(session),
+                    broken: false,
+                    _permit: permit,
+                });
+            }
+
+            log::warn!("WebDriver session stopped responding, closing it");
+            close_session(session).await;
+        }
+
+        let profile = profile.unwrap_or_else(|| profiles.random());
+        let capabilities = capabilities(
+            &self.config,
+            profiles.get(profile),
+            proxy.map(|proxy| proxies.webdriver_capability(proxy)),
+        );
+        let client = create_fantoccini_client(&self.config, capabilities).await?;
+
+        Ok(PooledSession {
+            pool: self,
+            session: Some(Session {
+                client,
+                pages: 0,
+                profile,
+                proxy,
+            }),
+            broken: false,
+            _permit: permit,
+        })
+    }
+
+    /// Closes idle sessions, the ones in use are closed when released.
+    pub async fn close(&self) {
+        let sessions: Vec<Session> = self.idle.lock().unwrap().drain(..).collect();
+        for session in sessions {
+            close_session(session).await;
+        }
+    }
+}
+
+impl Drop for SessionPool {
+    fn drop(&mut self) {
+        let sessions: Vec<Session> = match self.idle.get_mut() {
+            Ok(idle) => std::mem::take(idle),
+            Err(_) => return,
+        };
+        for session in sessions {
+            spawn_close_session(session);
+        }
+    }
+}
+
+impl PooledSession<'_> {
+    pub fn client(&self) -> &fantoccini::Client {
+        // It's taken out only in `release` and `drop`
+        &self.session.as_ref().unwrap().client
+    }
+
+    /// The session will be closed instead of reused, e.g. after an error left the browser in an unknown state.
+    pub fn discard(&mut self) {
+        self.broken = true;
+    }
+
+    /// Gives the session back to the pool, or closes it when it's broken or served enough pages.
+    pub async fn release(mut self) {
+        let mut session = match self.session.take() {
+            Some(v) => v,
+            None => return,
+        };
+        session.pages += 1;
+
+        if self.broken || session.pages >= self.pool.config.max_pages_per_session {
+            close_session(session).await;
+        } else {
+            self.pool.idle.lock().unwrap().push(session);
+        }
+    }
+}
+
+impl Drop for PooledSession<'_> {
+    fn drop(&mut self) {
+        // E.g. the download got cancelled by a timeout
+        if let Some(session) = self.session.take() {
+            spawn_close_session(session);
+        }
+    }
+}
+
+async fn close_session(session: Session) {
+    if let Err(error) = session.client.close().await {
+        log::warn!("Couldn't close WebDriver session. Cause: {:?}", error);
+    }
+}
+
+fn spawn_close_session(session: Session) {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn(close_session(session));
+        }
+        Err(_) => log::warn!("WebDriver session left open, there's no runtime to close it"),
+    }
+}
+
+async fn create_fantoccini_client(
+    config: &WebDriverConfig,
+    capabilities: Capabilities,
+) -> error_stack::Result<fantoccini::Client, CreateFantocciniError> {
+    let webdriver_url = &config.url;
+
+    // Connecting to webdriver
+    let fantoccini_client = ClientBuilder::native()
+        .capabilities(capabilities)
+        .connect(webdriver_url)
+        .await
+        .map_err(|error| {
+            match error {
+                fantoccini::error::NewSessionError::BadWebdriverUrl(error) => {
+                    error_stack::report!(error)
+                    .change_context(CreateFantocciniError::BadWebdriverUrl)
+                    .attach_printable(
+                        format!(
+                            "Url provided for connecting to selenium webdriver for fantoccini is wrong.
+                            Please check config file and if docker selenium webdriver is working properly
+                            Provided webdriver url: {}", webdriver_url
+                        )
+                    )
+                },
+                error => {
+                    error_stack::report!(error)
+                    .change_context(CreateFantocciniError::ConnectingToWebdriver)
+                    .attach_printable(
+                        format!(
+                            "Error occured while connecting to selenium webdriver.
+                            Please check config file and if docker selenium webdriver is working properly.
+                            Maybe webdriver is overloaded or misconfigured.
+                            Maybe it needs restarting.
+                            Provided webdriver url: {}", webdriver_url
+                        )
+                    )
+                }
+            }
+        })?;
+
+    Ok(fantoccini_client)
+}
+
+/// W3C capabilities with options of the given browser.
+/// User agent is set in the browser, as `Client::set_ua` changes only requests sent to the webdriver.
+/// Other headers of the profile can't be set, the browser sends its own,
+/// except Accept-Language which is used when the config doesn't set the language.
+/// `proxy` capability of the proxy pool replaces the proxy of the config.
+fn capabilities(
+    config: &WebDriverConfig,
+    profile: &BrowserProfile,
+    proxy: Option<serde_json::Value>,
+) -> Capabilities {
+    let mut args: Vec<String> = Vec::new();
+    let mut prefs = serde_json::Map::new();
+
+    let user_agent = &profile.user_agent;
+    let languages = config
+        .language
+        .clone()
+        .or_else(|| profile.header("accept-language").map(accept_languages));
+
+    match config.browser {
+        Browser::Chrome => {
+            args.push(format!("--user-agent={}", user_agent));
+            if config.headless {
+                args.push("--headless".to_owned());
+            }
+            if let Some(size) = config.window_size {
+                args.push(format!("--window-size={},{}", size.width, size.height));
+            }
+            if let Some(languages) = &languages {
+                let language = languages.split(',').next().unwrap_or_default();
+                args.push(format!("--lang={}", language));
+                prefs.insert("intl.accept_languages".to_owned(), json!(languages));
+            }
+            if config.disable_images {
+                prefs.insert(
+                    "profile.managed_default_content_settings.images".to_owned(),
+                    json!(2),
+                );
+            }
+        }
+        Browser::Firefox => {
+            prefs.insert("general.useragent.override".to_owned(), json!(user_agent));
+            if config.headless {
+                args.push("-headless".to_owned());
+            }
+            if let Some(size) = config.window_size {
+                args.push(format!("--width={}", size.width));
+                args.push(format!("--height={}", size.height));
+            }
+            if let Some(languages) = &languages {
+                prefs.insert("intl.accept_languages".to_owned(), json!(languages));
+            }
+            if config.disable_images {
+                prefs.insert("permissions.default.image".to_owned(), json!(2));
+            }
+        }
+    }
+
+    let mut capabilities = Capabilities::new();
+    match config.browser {
+        Browser::Chrome => {
+            capabilities.insert("browserName".to_owned(), json!("chrome"));
+            capabilities.insert(
+                "goog:chromeOptions".to_owned(),
+                json!({ "args": args, "prefs": prefs }),
+            );
+        }
+        Browser::Firefox => {
+            capabilities.insert("browserName".to_owned(), json!("firefox"));
+            capabilities.insert(
+                "moz:firefoxOptions".to_owned(),
+                json!({ "args": args, "prefs": prefs }),
+            );
+        }
+    }
+
+    let proxy = proxy.or_else(|| {
+        config
+            .proxy
+            .as_ref()
+            .map(|proxy| json!({ "proxyType": "manual", "httpProxy": proxy, "sslProxy": proxy }))
+    });
+    if let Some(proxy) = proxy {
+        capabilities.insert("proxy".to_owned(), proxy);
+    }
+
+    if let Some(timeout) = config.page_load_timeout_ms {
+        capabilities.insert("timeouts".to_owned(), json!({ "pageLoad": timeout }));
+    }
+
+    capabilities.extend(config.extra_capabilities.clone());
+    capabilities
+}
+
+/// Browsers take the languages of the Accept-Language header without weights,
+/// e.g. "pl-PL,pl;q=0.9,en;q=0.8" becomes "pl-PL,pl,en".
+fn accept_languages(header: &str) -> String {
+    header
+        .split(',')
+        .filter_map(|language| language.split(';').next())
+        .map(str::trim)
+        .filter(|language| !language.is_empty())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WindowSize;
+    use std::collections::HashMap;
+
+    fn profile(accept_language: Option<&str>) -> BrowserProfile {
+        BrowserProfile {
+            user_agent: "r-prices".to_owned(),
+            headers: accept_language
+                .map(|value| HashMap::from([("Accept-Language".to_owned(), value.to_owned())]))
+                .unwrap_or_default(),
+        }
+    }
+
+    #[test]
+    fn chrome_capabilities() {
+        let config = WebDriverConfig {
+            headless: true,
+            window_size: Some(WindowSize {
+                width: 1280,
+                height: 800,
+            }),
+            language: Some("pl-PL".to_owned()),
+            disable_images: true,
+            page_load_timeout_ms: Some(30_000),
+            ..Default::default()
+        };
+
+        let capabilities = capabilities(&config, &profile(Some("en-US,en;q=0.9")), None);
+
+        assert_eq!(capabilities["browserName"], json!("chrome"));
+        assert_eq!(
+            capabilities["goog:chromeOptions"]["args"],
+            json!([
+                "--user-agent=r-prices",
+                "--headless",
+                "--window-size=1280,800",
+                "--lang=pl-PL"
+            ])
+        );
+        assert_eq!(
+            capabilities["goog:chromeOptions"]["prefs"]
+                ["profile.managed_default_content_settings.images"],
+            json!(2)
+        );
+        assert_eq!(capabilities["timeouts"], json!({ "pageLoad": 30_000 }));
+        assert!(!capabilities.contains_key("proxy"));
+    }
+
+    #[test]
+    fn firefox_capabilities_with_overrides() {
+        let mut extra_capabilities = serde_json::Map::new();
+        extra_capabilities.insert("acceptInsecureCerts".to_owned(), json!(true));
+
+        let config = WebDriverConfig {
+            browser: Browser::Firefox,
+            proxy: Some("proxy.local:3128".to_owned()),
+            extra_capabilities,
+            ..Default::default()
+        };
+
+        let capabilities = capabilities(&config, &profile(Some("pl-PL,pl;q=0.9,en;q=0.8")), None);
+
+        assert_eq!(capabilities["browserName"], json!("firefox"));
+        assert_eq!(
+            capabilities["moz:firefoxOptions"]["prefs"]["intl.accept_languages"],
+            json!("pl-PL,pl,en")
+        );
+        assert_eq!(
+            capabilities["moz:firefoxOptions"]["prefs"]["general.useragent.override"],
+            json!("r-prices")
+        );
+        assert_eq!(
+            capabilities["proxy"]["httpProxy"],
+            json!("proxy.local:3128")
+        );
+        assert_eq!(capabilities["acceptInsecureCerts"], json!(true));
+    }
+}
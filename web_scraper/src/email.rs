@@ -1,3 +1,5 @@
+use bigdecimal::Signed;
+use database::decimal::Decimal;
 use database::models::price::{Availability, Price};
 use lettre::message::SinglePart;
 use lettre::transport::smtp::authentication::Credentials;
@@ -19,19 +21,8 @@ pub fn email_many(
         "Availability"
     };
 
-    let previous_state = if old_price.availability == Availability::Available {
-        let old_price_rounded = format!("{:.2}", old_price.value.unwrap());
-        format!("{} PLN", old_price_rounded)
-    } else {
-        old_price.availability.to_string()
-    };
-
-    let current_state = if new_price.availability == Availability::Available {
-        let new_price_rounded = format!("{:.2}", new_price.value.unwrap());
-        format!("{} PLN", new_price_rounded)
-    } else {
-        new_price.availability.to_string()
-    };
+    let previous_state = state_of_price(old_price);
+    let current_state = state_of_price(new_price);
 
     let single_part = include_str!("email.html")
         .replace("[WHAT_HAS_CHANGED]", what_has_changed)
@@ -82,6 +73,28 @@ pub fn email_many(
     }
 }
 
+fn state_of_price(price: &Price) -> String {
+    match (price.availability, &price.value) {
+        (Availability::Available, Some(value)) => format_price(value, price.currency.as_deref()),
+        (availability, _) => availability.to_string(),
+    }
+}
+
+fn format_price(value: &Decimal, currency: Option<&str>) -> String {
+    // `with_scale` truncates, so half of a cent is added first to round half up
+    let half_cent = bigdecimal::BigDecimal::new(5.into(), 3);
+    let value = if value.0.is_negative() {
+        &value.0 - &half_cent
+    } else {
+        &value.0 + &half_cent
+    }
+    .with_scale(2);
+    match currency {
+        Some(currency) => format!("{} {}", value, currency),
+        None => format!("{}", value),
+    }
+}
+
 fn crop_string(s: &str, length: usize) -> String {
     if s.len() <= length {
         s.to_owned()
@@ -113,4 +126,24 @@ mod tests {
     fn test4() {
         assert_eq!(crop_string("Hello World", 0), "...");
     }
+
+    #[test]
+    fn test5() {
+        let decimal = |s: &str| s.parse::<Decimal>().unwrap();
+        assert_eq!(format_price(&decimal("1299.5"), Some("PLN")), "1299.50 PLN");
+        assert_eq!(format_price(&decimal("19.99"), Some("EUR")), "19.99 EUR");
+        assert_eq!(format_price(&decimal("5"), None), "5.00");
+    }
+
+    #[test]
+    fn test6() {
+        let decimal = |s: &str| s.parse::<Decimal>().unwrap();
+        assert_eq!(
+            format_price(&decimal("1299.999"), Some("PLN")),
+            "1300.00 PLN"
+        );
+        assert_eq!(format_price(&decimal("19.994"), None), "19.99");
+        assert_eq!(format_price(&decimal("19.995"), None), "20.00");
+        assert_eq!(format_price(&decimal("-0.125"), None), "-0.13");
+    }
 }
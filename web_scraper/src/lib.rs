@@ -1,6 +1,13 @@
 pub mod config;
+pub mod cookies;
+pub mod downloader_memory;
 pub mod downloaders;
 pub mod email;
+pub mod offer_schedule;
 pub mod price_scraper;
+pub mod profiles;
+pub mod proxies;
+pub mod robots;
+pub mod scheduler;
 pub mod tasks;
 pub mod utils;
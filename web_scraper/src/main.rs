@@ -1,43 +1,60 @@
-use database::db::get_pool;
-use log::info;
-use std::time::Duration;
+use database::db::{get_pool, PostgresPool};
+use log::{error, info};
+use std::sync::Arc;
 use web_scraper::config::PriceScraperConfig;
 use web_scraper::price_scraper::PriceScraper;
-use web_scraper::tasks::update_all_offers_and_send_notifications;
+use web_scraper::tasks::check_due_offers_and_send_notifications;
 use web_scraper::utils::init_env_and_logging;
 
 ///////////////////////////////////////////////////////////////////////////////
 // Run function
 
 async fn run() {
-    loop {
-        // Get configuration and other stuff
-        info!("Getting configuration");
-        let price_scraper_config = PriceScraperConfig::default();
-
-        {
-            // Get things
-            let pool = get_pool(&price_scraper_config.database_url);
-            let conn = &pool.get().unwrap();
-            let scraper = PriceScraper::new(price_scraper_config.clone()).await;
-
-            // Run things
-            info!("Updating products");
-
-            let timer = std::time::Instant::now();
-            update_all_offers_and_send_notifications(&scraper, conn).await;
-            let elapsed_time = timer.elapsed().as_secs_f32();
-            info!("Updating prices took {} secs", elapsed_time);
-        }
+    // Get configuration and other stuff
+    info!("Getting configuration");
+    let price_scraper_config = PriceScraperConfig::default();
+
+    // Get things
+    let pool = get_pool(&price_scraper_config.database_url);
+    let scraper = Arc::new(PriceScraper::new(price_scraper_config.clone()).await);
+    let worker = price_scraper_config.schedule.worker_name();
+
+    // Offers the last run didn't finish are taken back
+    match release_claims(&pool, &worker) {
+        Ok(0) => {}
+        Ok(count) => info!("Resuming {} offers left by the last run", count),
+        Err(err) => error!("Couldn't release offers of the last run. Cause: {:?}", err),
+    }
 
-        // Break or sleep
-        if price_scraper_config.run_in_loop == false {
-            break;
-        } else {
-            info!("Sleeping {} seconds", price_scraper_config.interval);
-            tokio::time::sleep(Duration::from_secs(price_scraper_config.interval)).await;
+    // Run things. Every offer is checked when it's due, see `schedule` in the settings
+    info!("Checking offers as {}", worker);
+
+    let timer = std::time::Instant::now();
+    tokio::select! {
+        _ = check_due_offers_and_send_notifications(
+            Arc::clone(&scraper),
+            pool.clone(),
+            Arc::new(price_scraper_config),
+            worker.clone(),
+        ) => {}
+        _ = tokio::signal::ctrl_c() => {
+            // Other instances can take the offers being checked right away
+            info!("Stopping");
+            if let Err(err) = release_claims(&pool, &worker) {
+                error!("Couldn't release offers being checked. Cause: {:?}", err);
+            }
         }
     }
+    scraper.close().await;
+    let elapsed_time = timer.elapsed().as_secs_f32();
+    info!("Checking offers took {} secs", elapsed_time);
+}
+
+/// Claims are released before the scraper starts checking and after it stops.
+fn release_claims(pool: &PostgresPool, worker: &str) -> Result<usize, String> {
+    let conn = pool.get().map_err(|err| format!("{:?}", err))?;
+    database::models::offer::mutations::release_claims(&conn, worker)
+        .map_err(|err| format!("{:?}", err))
 }
 
 ///////////////////////////////////////////////////////////////////////////////
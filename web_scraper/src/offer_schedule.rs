@@ -0,0 +1,159 @@
+use database::models::price::{Availability, Price};
+
+use crate::config::ScheduleConfig;
+
+/// What a check found out about the offer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckOutcome {
+    /// Price, currency or availability differs from the last price
+    Changed,
+    Unchanged,
+    /// Out of stock, the page is gone or can't be scraped
+    Unavailable,
+    /// The shop or the network failed, nothing is known about the offer
+    Failed,
+}
+
+impl CheckOutcome {
+    /// `prices` are the prices of the offer from before the new one, newest first.
+    pub fn of_new_price(
+        value: Option<&database::decimal::Decimal>,
+        availability: Availability,
+        currency: Option<&str>,
+        prices: &[Price],
+    ) -> Self {
+        let changed = prices.first().is_none_or(|last| {
+            last.value.as_ref() != value
+                || last.availability != availability
+                || last.currency.as_deref() != currency
+        });
+
+        if changed {
+            CheckOutcome::Changed
+        } else if availability == Availability::Available {
+            CheckOutcome::Unchanged
+        } else {
+            CheckOutcome::Unavailable
+        }
+    }
+}
+
+/// Seconds until the next check of the offer. `default_interval` is the base interval
+/// of offers nobody gets notified about, `current` is the interval the offer had.
+pub fn next_interval(
+    config: &ScheduleConfig,
+    default_interval: u64,
+    current: u64,
+    outcome: CheckOutcome,
+    watched: bool,
+) -> u64 {
+    let base = if watched {
+        config.hot_interval_secs
+    } else {
+        default_interval
+    };
+    // Watched offers stay fresh as long as the product can be bought
+    let max = if watched && outcome != CheckOutcome::Unavailable {
+        default_interval
+    } else {
+        config.max_interval_secs
+    }
+    .max(base);
+
+    let interval = match outcome {
+        CheckOutcome::Changed => base,
+        CheckOutcome::Failed => current,
+        CheckOutcome::Unchanged | CheckOutcome::Unavailable => {
+            (current as f64 * config.backoff_multiplier) as u64
+        }
+    };
+
+    interval.max(base).min(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(value: &str, availability: Availability) -> Price {
+        Price {
+            id: 0,
+            offer_id: 0,
+            value: Some(value.parse().unwrap()),
+            created_at: chrono::NaiveDate::from_ymd_opt(2022, 8, 1)
+                .and_then(|v| v.and_hms_opt(12, 0, 0))
+                .unwrap(),
+            availability,
+            currency: Some("PLN".to_owned()),
+            suspicious: false,
+        }
+    }
+
+    #[test]
+    fn outcome_compares_with_the_last_price() {
+        let prices = vec![price("100.0", Availability::Available)];
+        let value = "100.0".parse().unwrap();
+
+        let outcome =
+            CheckOutcome::of_new_price(Some(&value), Availability::Available, Some("PLN"), &prices);
+        assert_eq!(outcome, CheckOutcome::Unchanged);
+
+        let cheaper = "90.0".parse().unwrap();
+        let outcome = CheckOutcome::of_new_price(
+            Some(&cheaper),
+            Availability::Available,
+            Some("PLN"),
+            &prices,
+        );
+        assert_eq!(outcome, CheckOutcome::Changed);
+
+        let prices = vec![price("100.0", Availability::Unavailable)];
+        let outcome = CheckOutcome::of_new_price(
+            Some(&value),
+            Availability::Unavailable,
+            Some("PLN"),
+            &prices,
+        );
+        assert_eq!(outcome, CheckOutcome::Unavailable);
+    }
+
+    #[test]
+    fn unchanged_offers_back_off() {
+        let config = ScheduleConfig::default();
+
+        assert_eq!(
+            next_interval(&config, 3600, 3600, CheckOutcome::Unchanged, false),
+            7200
+        );
+        assert_eq!(
+            next_interval(&config, 3600, 86_400, CheckOutcome::Unavailable, false),
+            86_400
+        );
+        assert_eq!(
+            next_interval(&config, 3600, 86_400, CheckOutcome::Changed, false),
+            3600
+        );
+        assert_eq!(
+            next_interval(&config, 3600, 7200, CheckOutcome::Failed, false),
+            7200
+        );
+    }
+
+    #[test]
+    fn watched_offers_are_checked_often() {
+        let config = ScheduleConfig::default();
+
+        assert_eq!(
+            next_interval(&config, 3600, 3600, CheckOutcome::Changed, true),
+            900
+        );
+        assert_eq!(
+            next_interval(&config, 3600, 3600, CheckOutcome::Unchanged, true),
+            3600
+        );
+        assert_eq!(
+            next_interval(&config, 3600, 3600, CheckOutcome::Unavailable, true),
+            7200
+        );
+    }
+}
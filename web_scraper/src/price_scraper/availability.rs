@@ -0,0 +1,105 @@
+use database::models::price::Availability;
+use scraper::{Html, Node, Selector};
+
+use crate::config::AvailabilityRule;
+
+/// Returns `None` when none of the markers of the rule were found on the page.
+pub fn find_availability(document: &Html, rule: &AvailabilityRule) -> Option<Availability> {
+    let text = visible_text(document).to_lowercase();
+
+    let found = |selectors: &[String], texts: &[String]| {
+        any_selector_matches(document, selectors)
+            || texts.iter().any(|t| text.contains(&t.to_lowercase()))
+    };
+
+    if found(
+        &rule.temporarily_unavailable_selectors,
+        &rule.temporarily_unavailable_texts,
+    ) {
+        Some(Availability::TemporarilyUnavailable)
+    } else if found(&rule.unavailable_selectors, &rule.unavailable_texts) {
+        Some(Availability::Unavailable)
+    } else {
+        None
+    }
+}
+
+fn any_selector_matches(document: &Html, selectors: &[String]) -> bool {
+    selectors
+        .iter()
+        .any(|css_selector| match Selector::parse(css_selector) {
+            Ok(selector) => document.select(&selector).next().is_some(),
+            Err(error) => {
+                log::warn!(
+                    "Couldn't parse availability css selector. Given css selector: {}. Cause: {:?}",
+                    css_selector,
+                    error
+                );
+                false
+            }
+        })
+}
+
+/// Text of the page without contents of scripts and styles.
+fn visible_text(document: &Html) -> String {
+    document
+        .root_element()
+        .descendants()
+        .filter_map(|node| match node.value() {
+            Node::Text(text) => Some((node, text)),
+            _ => None,
+        })
+        .filter(|(node, _)| {
+            !node.ancestors().any(|ancestor| match ancestor.value() {
+                Node::Element(el) => {
+                    matches!(el.name(), "script" | "style" | "noscript" | "template")
+                }
+                _ => false,
+            })
+        })
+        .map(|(_, text)| &**text)
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule() -> AvailabilityRule {
+        AvailabilityRule {
+            unavailable_selectors: vec![".sold-out".to_owned()],
+            unavailable_texts: vec!["Produkt niedostępny".to_owned()],
+            temporarily_unavailable_selectors: vec![],
+            temporarily_unavailable_texts: vec!["dostępny wkrótce".to_owned()],
+        }
+    }
+
+    fn availability_of(html: &str) -> Option<Availability> {
+        find_availability(&Html::parse_document(html), &rule())
+    }
+
+    #[test]
+    fn selector_and_text_markers() {
+        assert_eq!(
+            availability_of("<div class='sold-out'></div><span>99 zł</span>"),
+            Some(Availability::Unavailable)
+        );
+        assert_eq!(
+            availability_of("<p>PRODUKT NIEDOSTĘPNY</p>"),
+            Some(Availability::Unavailable)
+        );
+        assert_eq!(
+            availability_of("<p>Dostępny wkrótce</p>"),
+            Some(Availability::TemporarilyUnavailable)
+        );
+    }
+
+    #[test]
+    fn scripts_are_not_visible_text() {
+        assert_eq!(
+            availability_of("<script>var t = 'Produkt niedostępny';</script><p>99 zł</p>"),
+            None
+        );
+    }
+}
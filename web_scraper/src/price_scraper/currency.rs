@@ -0,0 +1,47 @@
+/// Symbols and names shops put next to the price, checked in order.
+const CURRENCY_MARKERS: &[(&str, &str)] = &[
+    ("zł", "PLN"),
+    ("zl", "PLN"),
+    ("€", "EUR"),
+    ("£", "GBP"),
+    ("kč", "CZK"),
+    ("us$", "USD"),
+    ("$", "USD"),
+];
+
+const CURRENCY_CODES: &[&str] = &[
+    "PLN", "EUR", "USD", "GBP", "CZK", "CHF", "SEK", "NOK", "DKK",
+];
+
+/// Guesses the currency from the text of the price, e.g. "1 299,00 zł" gives "PLN".
+pub fn detect_currency(text: &str) -> Option<String> {
+    let upper = text.to_uppercase();
+    let code = upper
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .find(|word| CURRENCY_CODES.contains(word));
+
+    if let Some(code) = code {
+        return Some(code.to_owned());
+    }
+
+    let lower = text.to_lowercase();
+    CURRENCY_MARKERS
+        .iter()
+        .find(|(marker, _)| lower.contains(marker))
+        .map(|(_, code)| (*code).to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_and_symbols() {
+        assert_eq!(detect_currency("1 299,00 zł").as_deref(), Some("PLN"));
+        assert_eq!(detect_currency("1299 PLN").as_deref(), Some("PLN"));
+        assert_eq!(detect_currency("€ 15,50").as_deref(), Some("EUR"));
+        assert_eq!(detect_currency("$19.99").as_deref(), Some("USD"));
+        assert_eq!(detect_currency("19.99 chf").as_deref(), Some("CHF"));
+        assert_eq!(detect_currency("1299,00"), None);
+    }
+}
@@ -1,18 +1,42 @@
+use crate::config::{
+    ArchiveMode, BotWallConfig, DomainRule, DownloaderKind, RedirectPolicy, RetryPolicy,
+};
+use crate::cookies::CookieJar;
+use crate::downloader_memory::DownloaderMemory;
+use crate::downloaders::archive::PageArchive;
+use crate::downloaders::bot_wall::check_bot_wall;
 use crate::downloaders::fantoccini::FantocciniDownloader;
 use crate::downloaders::reqwest::ReqwestDownloader;
+use crate::downloaders::webdriver_pool::SessionPool;
+use crate::downloaders::{check_status, DownloadedPage, DownloadingError, RetryAfter};
+use crate::profiles::ProfilePool;
+use crate::proxies::ProxyPool;
+use crate::robots::RobotsCache;
+use crate::scheduler::Scheduler;
 use crate::{config::PriceScraperConfig, downloaders::Downloader};
-use std::collections::HashMap;
-use tokio::time::{sleep, Duration};
+use database::decimal::Decimal;
+use database::models::price::Availability;
+use redirects::Landing;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
 
 ///////////////////////////////////////////////////////////////////////////////
 // Private Modules
 
+mod availability;
+mod currency;
+mod price_parser;
+mod redirects;
+mod structured_data;
 mod utils;
 
 ///////////////////////////////////////////////////////////////////////////////
 // Public Errors
 
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[error("Cannot get price from the page")]
 pub enum GetPriceError {
     PageNotSupported,
@@ -20,6 +44,13 @@ pub enum GetPriceError {
     ErrorDownloadingPage,
     PageDownloadTimeout,
     Redirected,
+    /// The shop responded with 404 or 410
+    PageNotFound,
+    DisallowedByRobots,
+    /// The shop refused to serve the page
+    Blocked,
+    ServerError,
+    RateLimited,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -41,16 +72,47 @@ pub enum GetPotentialPricesError {
 // Private Errors
 
 #[derive(thiserror::Error, Debug)]
-#[error("Selector not found for this page. Probably it's not supported.")]
-struct GetSelectorError {}
+#[error("Domain rule not found for this page. Probably it's not supported.")]
+struct GetDomainRuleError {}
 
 ///////////////////////////////////////////////////////////////////////////////
 // Structs
 
+/// Price might be still displayed when the product is out of stock, and vice versa
+/// the shop might tell the product is unavailable without showing any price.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrapedPrice {
+    pub value: Option<Decimal>,
+    pub availability: Availability,
+    /// ISO 4217 code. E.g. "PLN"
+    pub currency: Option<String>,
+    /// Differs a lot from the last price, it needs a confirmation
+    pub suspicious: bool,
+    /// The shop redirected to another url of the same product
+    pub moved_to: Option<String>,
+}
+
 pub struct PriceScraper {
-    reqwest_selectors: HashMap<String, String>,
-    fantoccini_selectors: HashMap<String, String>,
+    domain_rules: Vec<DomainRule>,
+    /// Used to download pages of shops without a rule, hoping for structured data
+    default_rule: DomainRule,
+    /// Downloads every page instead of the downloader configured for the shop
+    downloader_override: Option<Box<dyn Downloader + Send + Sync>>,
+    archive: Option<PageArchive>,
+    archive_mode: ArchiveMode,
+    /// Keeps downloads from one shop from running all at once
+    scheduler: Scheduler,
+    robots: RobotsCache,
+    retry_policy: RetryPolicy,
+    redirect_policy: RedirectPolicy,
+    bot_wall: BotWallConfig,
+    /// Shops which needed the browser, downloaded with it directly
+    downloader_memory: DownloaderMemory,
+    pub profiles: ProfilePool,
+    pub proxies: ProxyPool,
+    pub cookies: Arc<CookieJar>,
     pub reqwest_client: reqwest::Client,
+    pub webdriver_pool: SessionPool,
 }
 
 impl PriceScraper {
@@ -58,193 +120,613 @@ impl PriceScraper {
     // PUBLIC
 
     pub async fn new(config: PriceScraperConfig) -> Self {
-        let reqwest_client = reqwest::ClientBuilder::new()
-            .user_agent(&config.user_agent)
-            // TODO: timeout from config
-            .timeout(std::time::Duration::from_secs(20))
-            .build()
-            .unwrap_or_else(|e| {
-                panic!("Error while trying building a reqwest client: {:?}", e);
-            });
+        let profiles = ProfilePool::new(&config);
+        let cookies = Arc::new(CookieJar::load(
+            config
+                .cookies
+                .as_ref()
+                .map(|cookies| PathBuf::from(&cookies.file)),
+            &config.domains,
+        ));
+        let reqwest_client = build_reqwest_client(&config.user_agent, &cookies, None);
+        let proxies = ProxyPool::new(config.proxies, |proxy| {
+            build_reqwest_client(&config.user_agent, &cookies, Some(proxy))
+        });
 
         Self {
-            reqwest_selectors: config.reqwest_selectors,
-            fantoccini_selectors: config.fantoccini_selectors,
+            domain_rules: config.domains,
+            default_rule: DomainRule::default(),
+            downloader_override: None,
+            archive: config
+                .archive
+                .as_ref()
+                .map(|archive| PageArchive::new(&archive.dir)),
+            archive_mode: config
+                .archive
+                .as_ref()
+                .map(|archive| archive.mode)
+                .unwrap_or_default(),
+            scheduler: Scheduler::new(config.scheduler),
+            robots: RobotsCache::new(profiles.user_agents(), &config.robots),
+            retry_policy: config.retry,
+            redirect_policy: config.redirects,
+            bot_wall: config.bot_wall,
+            downloader_memory: DownloaderMemory::load(&config.downloader_memory),
+            cookies,
             reqwest_client,
+            webdriver_pool: SessionPool::new(config.webdriver),
+            profiles,
+            proxies,
         }
     }
 
+    /// Makes the scraper download every page with the given downloader, e.g. to serve saved pages.
+    pub fn with_downloader(mut self, downloader: Box<dyn Downloader + Send + Sync>) -> Self {
+        self.downloader_override = Some(downloader);
+        self
+    }
+
     /// This is the main function you want to use.
     pub async fn get_price(
         &self,
         url: &str,
-        last_available_price: Option<f64>,
-    ) -> error_stack::Result<f64, GetPriceError> {
-        // TODO: First sleep time in the some config.json
-        // Exponential sleep duration. 10 secs
-        let mut duration: u64 = 10;
-
-        // TODO: Move this into config
-        let fairness_tries = 3;
-
-        // Get price, first try
-        let mut price = self.get_price_retry_error(url).await?;
-
-        // Loop if the price seems not fair, suspicious
-        for _ in 0..fairness_tries {
-            let last_price = match last_available_price {
-                Some(v) => v,
-                // If there's no last available price return gotten price early
-                None => return Ok(price),
-            };
+        last_available_price: Option<&Decimal>,
+    ) -> error_stack::Result<ScrapedPrice, GetPriceError> {
+        let policy = self.retry_policy(url);
 
-            let percent_difference = ((price / last_price) - 1.0).abs();
+        let mut price = self.get_price_retry_error(url, policy).await?;
 
-            // TODO: Condition in some config
-            // If difference in price is not so much different then break
-            if percent_difference < 0.10 {
-                break;
+        let suspicious = match (last_available_price, &price.value) {
+            (Some(last_price), Some(new_price)) => {
+                utils::is_suspicious_change(last_price, new_price, policy.suspicious_change_percent)
             }
+            // If there's nothing to compare the price is fine
+            _ => false,
+        };
 
-            // But in the other case, price might be suspiciously different
-
-            // Sleep for `duration` (secs) time
-            sleep(Duration::from_secs(duration)).await;
-            // TODO: Multiplier in the some config.json
-            duration *= 2;
-
-            // Try to get price again
-            price = self.get_price_retry_error(url).await?;
-        }
-
+        // The shop might have really changed the price, or the page got broken.
+        // It's up to the caller to wait for a confirmation
+        price.suspicious = suspicious;
         Ok(price)
     }
 
+    /// Tells if the new price differs from the last one enough to need a confirmation,
+    /// according to the retry policy of the shop.
+    pub fn is_suspicious_change(
+        &self,
+        url: &str,
+        last_price: &Decimal,
+        new_price: &Decimal,
+    ) -> bool {
+        utils::is_suspicious_change(
+            last_price,
+            new_price,
+            self.retry_policy(url).suspicious_change_percent,
+        )
+    }
+
     /// Maybe you want some debugging info, so you can get blocks on the page, that bot thinks there are prices in
     pub async fn get_potential_prices_blocks(
         &self,
         url: &str,
     ) -> error_stack::Result<Vec<String>, GetPotentialPricesError> {
-        // Get downloader function and css selector
-        let (downloader, css_selector) = self
-            .get_downloader_and_css_selector(url)
-            .map_err(|error| {
-                error
-                    .change_context(GetPotentialPricesError::PageNotSupported)
-                    .attach_printable(format!("Cannot get css selector for this url from config file, probably this domain is not supported. Url: {}", url))
-            })?;
-
-        // Dowload page
-        let page = downloader.download_page(self, url).await.map_err(|error| {
-            error_stack::report!(error)
-                .change_context(GetPotentialPricesError::CannotDownloadPage)
-                .attach_printable("Cannot download the page")
-                .attach_printable(format!("Url: {}", url))
+        // Get rule of the shop
+        let rule = self.get_domain_rule(url).map_err(|error| {
+            error
+                .change_context(GetPotentialPricesError::PageNotSupported)
+                .attach_printable(format!("Cannot get rule for this url from config file, probably this domain is not supported. Url: {}", url))
         })?;
 
-        // Construct html scraper
-        let scraper_selector = scraper::Selector::parse(css_selector).map_err(|error| {
-            error_stack::report!(GetPotentialPricesError::CannotParseCssSelector)
-                .attach_printable(format!(
-                    "Couldn't parse css selector. Given css selector: {}",
-                    css_selector
-                ))
-                .attach_printable(format!("Cause: {:?}", error))
-        })?;
+        // Dowload page
+        let page = self
+            .download_page_with_rule(rule, url, None)
+            .await
+            .map_err(|error| {
+                error_stack::report!(error)
+                    .change_context(GetPotentialPricesError::CannotDownloadPage)
+                    .attach_printable("Cannot download the page")
+                    .attach_printable(format!("Url: {}", url))
+            })?;
 
         // Parse document
-        let source = scraper::Html::parse_document(&page);
-
-        // Find potential matches
-        let matches = source.select(&scraper_selector);
+        let source = scraper::Html::parse_document(&page.html);
+
+        // Find potential matches of every selector
+        let mut matches = Vec::new();
+        for css_selector in &rule.price_selectors {
+            let scraper_selector = scraper::Selector::parse(css_selector).map_err(|error| {
+                error_stack::report!(GetPotentialPricesError::CannotParseCssSelector)
+                    .attach_printable(format!(
+                        "Couldn't parse css selector. Given css selector: {}",
+                        css_selector
+                    ))
+                    .attach_printable(format!("Cause: {:?}", error))
+            })?;
 
-        // Convert matches to vec of strings
-        let matches = matches
-            .map(|el| el.text().collect::<String>())
-            .collect::<Vec<String>>();
+            matches.extend(
+                source
+                    .select(&scraper_selector)
+                    .map(|el| el.text().collect::<String>()),
+            );
+        }
 
         // Return matches
         Ok(matches)
     }
 
+    /// Closes browser sessions kept open between pages, saves cookies and the downloader memory. Call it when the scraper
+    /// isn't needed anymore, otherwise sessions are closed in the background and might outlive the program.
+    pub async fn close(&self) {
+        self.webdriver_pool.close().await;
+        self.save_state();
+    }
+
+    /// Saves cookies and the downloader memory, so a long run doesn't lose them when it gets killed.
+    pub fn save_state(&self) {
+        if let Err(error) = self.cookies.save() {
+            log::error!("{:?}", error);
+        }
+        if let Err(error) = self.downloader_memory.save() {
+            log::error!("{:?}", error);
+        }
+    }
+
+    /// Downloads the page the same way as when getting the price.
+    pub async fn download_page(
+        &self,
+        url: &str,
+    ) -> error_stack::Result<DownloadedPage, DownloadingError> {
+        let rule = self.get_domain_rule(url).unwrap_or(&self.default_rule);
+        self.download_page_with_rule(rule, url, None).await
+    }
+
+    /// Single try, without retrying on errors and checking if the price is fair.
+    /// Useful with downloaders serving saved pages, where trying again doesn't change anything.
+    pub async fn get_price_once(
+        &self,
+        url: &str,
+    ) -> error_stack::Result<ScrapedPrice, GetPriceError> {
+        // Pages of shops without a rule can still be read from structured data
+        let rule = self.get_domain_rule(url).ok();
+        let download_rule = rule.unwrap_or(&self.default_rule);
+
+        if download_rule.downloader != DownloaderKind::Adaptive
+            || self.downloader_override.is_some()
+        {
+            return self.scrape_price(url, rule, None).await;
+        }
+
+        let kind = self.downloader_kind(download_rule, url);
+        let price = self.scrape_price(url, rule, Some(kind)).await;
+
+        // Pages rendered by javascript have no price for reqwest
+        let browser_needed = kind == DownloaderKind::Reqwest
+            && price.as_ref().is_err_and(|error| {
+                matches!(
+                    error.current_context(),
+                    GetPriceError::PriceNotFound | GetPriceError::Blocked
+                )
+            });
+        if !browser_needed {
+            // Reqwest managed again, the shop doesn't need the browser anymore
+            if price.is_ok() && kind == DownloaderKind::Reqwest {
+                self.downloader_memory
+                    .remember(&download_rule.domain_key(url), kind);
+            }
+            return price;
+        }
+
+        log::info!(
+            "Reqwest didn't get the price, trying the browser. Url: {}",
+            url
+        );
+        let price = self
+            .scrape_price(url, rule, Some(DownloaderKind::Fantoccini))
+            .await;
+        if price.is_ok() {
+            self.downloader_memory
+                .remember(&download_rule.domain_key(url), DownloaderKind::Fantoccini);
+        }
+        price
+    }
+
+    /// Downloads the page and reads the price, `kind` overrides the downloader of the shop.
+    async fn scrape_price(
+        &self,
+        url: &str,
+        rule: Option<&DomainRule>,
+        kind: Option<DownloaderKind>,
+    ) -> error_stack::Result<ScrapedPrice, GetPriceError> {
+        let download_rule = rule.unwrap_or(&self.default_rule);
+
+        // Download page
+        let page = match self.download_page_with_rule(download_rule, url, kind).await {
+            Ok(v) => v,
+            Err(error) => {
+                let context = match error.current_context() {
+                    DownloadingError::Redirection => GetPriceError::Redirected,
+                    DownloadingError::Timeout => GetPriceError::PageDownloadTimeout,
+                    DownloadingError::DisallowedByRobots => GetPriceError::DisallowedByRobots,
+                    DownloadingError::NotFound => GetPriceError::PageNotFound,
+                    DownloadingError::Blocked => GetPriceError::Blocked,
+                    DownloadingError::ServerError => GetPriceError::ServerError,
+                    DownloadingError::RateLimited => GetPriceError::RateLimited,
+                    _ => GetPriceError::ErrorDownloadingPage,
+                };
+                let price = Err(error
+                    .change_context(context)
+                    .attach_printable(format!("Url: {}", url)));
+
+                // Pages with an error status are attached to the error
+                if let Some(page) = price.as_ref().err().and_then(|e| e.downcast_ref()) {
+                    self.archive_page(url, page, &price).await;
+                }
+                return price;
+            }
+        };
+
+        let price = self
+            .check_landing(url, &page, rule)
+            .and_then(|moved_to| {
+                extract_price(&page.html, rule).map(|price| ScrapedPrice { moved_to, ..price })
+            })
+            .map_err(|error| error.attach_printable(format!("Url: {}", url)));
+
+        self.archive_page(url, &page, &price).await;
+
+        price
+    }
+
     /////////////////////////////////////////////////////////////////////////////////////////////////////////
     // PRIVATE
 
-    async fn get_price_retry_error(&self, url: &str) -> error_stack::Result<f64, GetPriceError> {
-        // TODO: First sleep time in the some config.json
-        // Exponential sleep duration. 10 secs
-        let mut duration: u64 = 10;
+    async fn get_price_retry_error(
+        &self,
+        url: &str,
+        policy: &RetryPolicy,
+    ) -> error_stack::Result<ScrapedPrice, GetPriceError> {
+        let mut attempt = 0;
+
+        loop {
+            let error = match self.get_price_once(url).await {
+                Ok(v) => return Ok(v),
+                Err(error) => error,
+            };
 
-        // TODO: Move this into config
-        let number_of_tries = 3;
+            // E.g. robots.txt or a missing rule don't change between tries
+            if attempt >= policy.retries || !policy.is_retryable(error.current_context()) {
+                return Err(error);
+            }
 
-        // Get price, first try
-        let mut price = self.get_price_once(url).await;
+            // The shop wants a longer break than we are going to wait
+            if let Some(RetryAfter(delay)) = error.downcast_ref() {
+                if delay.as_millis() > policy.max_backoff_ms as u128 {
+                    return Err(error);
+                }
+            }
 
-        if let Ok(v) = price {
-            return Ok(v);
+            sleep(policy.backoff(attempt)).await;
+            attempt += 1;
         }
+    }
 
-        // Loop if the price seems not fair, suspicious
-        for _ in 0..number_of_tries {
-            // Try to get price
-            price = self.get_price_once(url).await;
+    /// Policy of the shop, or the global one.
+    fn retry_policy(&self, url: &str) -> &RetryPolicy {
+        self.get_domain_rule(url)
+            .ok()
+            .and_then(|rule| rule.retry.as_ref())
+            .unwrap_or(&self.retry_policy)
+    }
 
-            if price.is_ok() {
-                break;
-            }
+    /// Tells where the shop sent us. Gives the new url of the offer
+    /// when the shop redirected to another page of the same product.
+    fn check_landing(
+        &self,
+        url: &str,
+        page: &DownloadedPage,
+        rule: Option<&DomainRule>,
+    ) -> error_stack::Result<Option<String>, GetPriceError> {
+        let (requested, landed) = match (url::Url::parse(url), url::Url::parse(&page.url)) {
+            (Ok(requested), Ok(landed)) => (requested, landed),
+            // The downloader already checked the url, there's nothing to compare otherwise
+            _ => return Ok(None),
+        };
+
+        let policy = rule
+            .and_then(|rule| rule.redirects.as_ref())
+            .unwrap_or(&self.redirect_policy);
+
+        match redirects::classify(&requested, &landed, &page.html, rule, policy) {
+            Landing::Requested => Ok(None),
+            Landing::Moved(new_url) => Ok(Some(new_url)),
+            Landing::Elsewhere => Err(error_stack::report!(GetPriceError::Redirected)
+                .attach_printable("Shop redirected to a page that isn't the product")
+                .attach_printable(format!("Downloaded url: {}", page.url))),
+        }
+    }
 
-            // But in the other case, price might be suspiciously different
-            // Sleep for `duration` (secs) time
-            sleep(Duration::from_secs(duration)).await;
-            // TODO: Multiplier in the some config.json
-            duration *= 2;
+    /// Failures of archiving are only logged, they shouldn't stop scraping.
+    async fn archive_page(
+        &self,
+        url: &str,
+        page: &DownloadedPage,
+        price: &error_stack::Result<ScrapedPrice, GetPriceError>,
+    ) {
+        let archive = match &self.archive {
+            Some(v) => v,
+            None => return,
+        };
+
+        let scrape_error = price
+            .as_ref()
+            .err()
+            .map(|error| format!("{:?}", error.current_context()));
+
+        if scrape_error.is_none() && self.archive_mode == ArchiveMode::Failed {
+            return;
         }
 
-        price
+        if let Err(error) = archive.save(url, page, scrape_error).await {
+            log::warn!(
+                "Couldn't archive the page. Url: {}. Cause: {:?}",
+                url,
+                error
+            );
+        }
     }
 
-    async fn get_price_once(&self, url: &str) -> error_stack::Result<f64, GetPriceError> {
-        Ok(self
-            .get_potential_prices_blocks(url)
-            .await
-            .map_err(|error| error.change_context(GetPriceError::ErrorDownloadingPage))?
-            .iter()
-            .flat_map(|s| utils::string_to_float(s))
-            .next()
-            .ok_or(GetPriceError::PriceNotFound)?)
+    /// `kind` overrides the downloader of the shop.
+    async fn download_page_with_rule(
+        &self,
+        rule: &DomainRule,
+        url: &str,
+        kind: Option<DownloaderKind>,
+    ) -> error_stack::Result<DownloadedPage, DownloadingError> {
+        // Saved pages don't need to be downloaded politely
+        if let Some(downloader) = &self.downloader_override {
+            return downloader
+                .download_page(self, rule, url)
+                .await
+                .and_then(check_status)
+                .and_then(|page| check_bot_wall(page, &self.bot_wall));
+        }
+
+        self.check_robots(rule, url).await?;
+
+        let kind = kind.unwrap_or_else(|| self.downloader_kind(rule, url));
+        let page = self.download_politely(kind, rule, url).await;
+
+        // Bot walls often let browsers through. Adaptive shops switch by themselves
+        let blocked = page
+            .as_ref()
+            .is_err_and(|error| *error.current_context() == DownloadingError::Blocked);
+        if blocked
+            && kind == DownloaderKind::Reqwest
+            && rule.downloader == DownloaderKind::Reqwest
+            && self.bot_wall.escalate_to_browser
+        {
+            log::warn!(
+                "Shop blocked reqwest, its pages will be downloaded with the browser. Url: {}",
+                url
+            );
+            self.downloader_memory
+                .remember(&rule.domain_key(url), DownloaderKind::Fantoccini);
+            return self
+                .download_politely(DownloaderKind::Fantoccini, rule, url)
+                .await;
+        }
+
+        page
     }
 
-    fn get_downloader_and_css_selector<'a>(
-        &'a self,
+    /// Downloader of the shop, the browser when the shop needed it lately.
+    /// Never adaptive, adaptive shops start with reqwest.
+    fn downloader_kind(&self, rule: &DomainRule, url: &str) -> DownloaderKind {
+        match rule.downloader {
+            DownloaderKind::Fantoccini => DownloaderKind::Fantoccini,
+            DownloaderKind::Reqwest | DownloaderKind::Adaptive => {
+                if self.downloader_memory.needs_browser(&rule.domain_key(url)) {
+                    DownloaderKind::Fantoccini
+                } else {
+                    DownloaderKind::Reqwest
+                }
+            }
+        }
+    }
+
+    /// Waits for the scheduler, then downloads and checks the page.
+    async fn download_politely(
+        &self,
+        kind: DownloaderKind,
+        rule: &DomainRule,
         url: &str,
-    ) -> error_stack::Result<(Box<dyn Downloader>, &'a str), GetSelectorError> {
-        // First look for selector in reqwest_selectors
-        let reqwest_selector = self
-            .reqwest_selectors
-            .iter()
-            .find(|(k, _)| url.contains(*k));
+    ) -> error_stack::Result<DownloadedPage, DownloadingError> {
+        let downloader: &(dyn Downloader + Send + Sync) = match kind {
+            DownloaderKind::Reqwest | DownloaderKind::Adaptive => &ReqwestDownloader,
+            DownloaderKind::Fantoccini => &FantocciniDownloader,
+        };
+
+        let _slot = self.scheduler.acquire(rule, url).await;
+        let page = downloader
+            .download_page(self, rule, url)
+            .await
+            .and_then(check_status)
+            .and_then(|page| check_bot_wall(page, &self.bot_wall));
 
-        if let Some((_, v)) = reqwest_selector {
-            return Ok((Box::new(ReqwestDownloader), v));
+        if let Some(RetryAfter(delay)) = page.as_ref().err().and_then(|e| e.downcast_ref()) {
+            // Long pauses are cut to the longest backoff, so one shop doesn't hold up the whole run
+            let limit = Duration::from_millis(self.retry_policy(url).max_backoff_ms);
+            self.scheduler.hold_off(rule, url, (*delay).min(limit));
         }
 
-        // Then look for selector in fantoccini_selectors
-        let fantoccini_selector = self
-            .fantoccini_selectors
-            .iter()
-            .find(|(k, _)| url.contains(*k));
+        page
+    }
 
-        if let Some((_, v)) = fantoccini_selector {
-            return Ok((Box::new(FantocciniDownloader), v));
+    /// Checked against the user agents of all profiles, any of them might download the page.
+    /// Crawl-delay of the shop is passed to the scheduler.
+    async fn check_robots(
+        &self,
+        rule: &DomainRule,
+        url: &str,
+    ) -> error_stack::Result<(), DownloadingError> {
+        let url_struct = url::Url::parse(url).map_err(|error| {
+            error_stack::report!(error)
+                .change_context(DownloadingError::NotValidInputUrl)
+                .attach_printable(format!("Given url is not valid. Url: {}", url))
+        })?;
+
+        // Robots.txt goes the same way as the page, the shop might block our server
+        let client = match self.proxies.route(rule, url) {
+            Some(proxy) => self.proxies.client(proxy),
+            None => &self.reqwest_client,
+        };
+        let robots = self
+            .robots
+            .rules(client, &self.scheduler, rule, &url_struct)
+            .await;
+
+        if let Some(delay) = robots.crawl_delay() {
+            self.scheduler.respect_crawl_delay(rule, url, delay);
         }
 
-        // If not found, this means page is not supported
-        return Err(
-            error_stack::report!(GetSelectorError {}).attach_printable(format!(
+        let path = match url_struct.query() {
+            Some(query) => format!("{}?{}", url_struct.path(), query),
+            None => url_struct.path().to_owned(),
+        };
+
+        if robots.is_allowed(&path) {
+            Ok(())
+        } else {
+            Err(
+                error_stack::report!(DownloadingError::DisallowedByRobots).attach_printable(
+                    format!("Robots.txt of the shop disallows the url. Url: {}", url),
+                ),
+            )
+        }
+    }
+
+    fn get_domain_rule(&self, url: &str) -> error_stack::Result<&DomainRule, GetDomainRuleError> {
+        let url_struct = url::Url::parse(url).map_err(|error| {
+            error_stack::report!(GetDomainRuleError {})
+                .attach_printable(format!("Url is not valid. Url: {}", url))
+                .attach_printable(format!("Cause: {:?}", error))
+        })?;
+
+        DomainRule::find(&self.domain_rules, &url_struct).ok_or_else(|| {
+            error_stack::report!(GetDomainRuleError {}).attach_printable(format!(
                 "Page with domain of that url was not found in web_scraper_settings.json. Url: {}",
                 url
+            ))
+        })
+    }
+}
+
+fn build_reqwest_client(
+    user_agent: &str,
+    cookies: &Arc<CookieJar>,
+    proxy: Option<reqwest::Proxy>,
+) -> reqwest::Client {
+    let builder = reqwest::ClientBuilder::new()
+        .user_agent(user_agent)
+        .cookie_provider(Arc::clone(cookies))
+        // TODO: timeout from config
+        .timeout(std::time::Duration::from_secs(20));
+
+    let builder = match proxy {
+        Some(proxy) => builder.proxy(proxy),
+        None => builder,
+    };
+
+    builder.build().unwrap_or_else(|e| {
+        panic!("Error while trying building a reqwest client: {:?}", e);
+    })
+}
+
+/// Markers configured for the shop decide about availability first, then structured data.
+/// Structured data comes first when looking for the price, as it's the most reliable source,
+/// css selectors are a fallback. The currency given in structured data is trusted the most,
+/// then the one configured for the shop, and at last the one guessed from the price text.
+fn extract_price(
+    page: &str,
+    rule: Option<&DomainRule>,
+) -> error_stack::Result<ScrapedPrice, GetPriceError> {
+    let document = scraper::Html::parse_document(page);
+    let structured_offer = structured_data::find_offer(&document);
+
+    let availability = rule
+        .and_then(|rule| availability::find_availability(&document, &rule.availability))
+        .or(structured_offer.availability);
+
+    let (value, price_text) = match (structured_offer.price, rule) {
+        (Some(price), _) => (Some(price), None),
+        (None, Some(rule)) => match find_price_with_selectors(&document, rule)? {
+            Some((price, text)) => (Some(price), Some(text)),
+            None => (None, None),
+        },
+        (None, None) => (None, None),
+    };
+
+    let currency = structured_offer
+        .currency
+        .or_else(|| rule.and_then(|rule| rule.currency.clone()))
+        .or_else(|| price_text.and_then(|text| currency::detect_currency(&text)));
+
+    match (value, availability) {
+        (Some(value), availability) => Ok(ScrapedPrice {
+            value: Some(value),
+            availability: availability.unwrap_or(Availability::Available),
+            currency,
+            suspicious: false,
+            moved_to: None,
+        }),
+        // The shop tells there's nothing to buy, so no price is expected
+        (None, Some(availability)) if availability != Availability::Available => {
+            Ok(ScrapedPrice {
+                value: None,
+                availability,
+                currency: None,
+                suspicious: false,
+                moved_to: None,
+            })
+        }
+        (None, _) => match rule {
+            None => Err(error_stack::report!(GetPriceError::PageNotSupported).attach_printable(
+                "No structured data with price on the page and the domain has no rule in web_scraper_settings.json",
             )),
-        );
+            Some(rule) => Err(error_stack::report!(GetPriceError::PriceNotFound)
+                .attach_printable(format!("Tried css selectors: {:?}", rule.price_selectors))),
+        },
     }
 }
+
+/// Returns the price with the text it was parsed from.
+fn find_price_with_selectors(
+    document: &scraper::Html,
+    rule: &DomainRule,
+) -> error_stack::Result<Option<(Decimal, String)>, GetPriceError> {
+    for css_selector in &rule.price_selectors {
+        let scraper_selector = scraper::Selector::parse(css_selector).map_err(|error| {
+            error_stack::report!(GetPriceError::PriceNotFound)
+                .attach_printable(format!(
+                    "Couldn't parse css selector. Given css selector: {}",
+                    css_selector
+                ))
+                .attach_printable(format!("Cause: {:?}", error))
+        })?;
+
+        let price = document
+            .select(&scraper_selector)
+            .map(|el| el.text().collect::<String>())
+            .find_map(|s| {
+                price_parser::parse_price(&s, rule.decimal_separator)
+                    .ok()
+                    .map(|price| (price, s))
+            });
+
+        if price.is_some() {
+            return Ok(price);
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,281 @@
+use database::decimal::Decimal;
+use std::str::FromStr;
+
+#[derive(thiserror::Error, Debug)]
+#[error("Cannot parse the price")]
+pub enum ParsePriceError {
+    NoNumber,
+    InvalidNumber,
+}
+
+/// Separators allowed between groups of digits, next to `.` and `,`.
+const GROUPING_CHARS: &[char] = &[' ', '\u{a0}', '\u{202f}', '\u{2009}', '\''];
+
+const SUPERSCRIPT_DIGITS: &[char] = &['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+
+/// Reads the first price found in the text, e.g. "od 1 299,00 zł", "from $1,299.99",
+/// "1299⁹⁹" or "100 - 200 zł" (the lower bound of a range is taken).
+///
+/// When `decimal_separator` is not known it's guessed: when both `.` and `,` are present
+/// the last one is the decimal separator, a single separator followed by exactly three digits
+/// is a thousands separator, otherwise it's a decimal one.
+pub fn parse_price(
+    s: &str,
+    decimal_separator: Option<char>,
+) -> error_stack::Result<Decimal, ParsePriceError> {
+    let (token, cents) = first_number(s).ok_or_else(|| {
+        error_stack::report!(ParsePriceError::NoNumber)
+            .attach_printable(format!("There's no number in the string. String: {}", s))
+    })?;
+
+    // Superscript cents mean every separator of the token is a grouping one
+    let number = match cents {
+        Some(cents) => format!("{}.{}", digits_only(&token), cents),
+        None => normalize(&token, decimal_separator).ok_or_else(|| {
+            error_stack::report!(ParsePriceError::InvalidNumber)
+                .attach_printable(format!("Ambiguous separators in the number: {}", token))
+                .attach_printable(format!("String: {}", s))
+        })?,
+    };
+
+    Decimal::from_str(&number).map_err(|error| {
+        error_stack::report!(ParsePriceError::InvalidNumber)
+            .attach_printable(format!("Cannot parse string to decimal. String: {}", s))
+            .attach_printable(format!("Cause: {:?}", error))
+    })
+}
+
+/// Returns the first run of digits and separators, with superscript digits following it.
+fn first_number(s: &str) -> Option<(String, Option<String>)> {
+    let chars: Vec<char> = s.chars().collect();
+    let start = chars.iter().position(|c| c.is_ascii_digit())?;
+
+    let mut token = String::new();
+    let mut i = start;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_ascii_digit() || c == '.' || c == ',' {
+            token.push(c);
+        } else if GROUPING_CHARS.contains(&c) && is_digit_group_at(&chars, i + 1) {
+            // A space belongs to the number only when a group of three digits follows it
+            token.push(c);
+        } else {
+            break;
+        }
+        i += 1;
+    }
+
+    let cents: String = chars[i..]
+        .iter()
+        .take_while(|c| SUPERSCRIPT_DIGITS.contains(c))
+        .map(|c| {
+            let digit = SUPERSCRIPT_DIGITS.iter().position(|d| d == c).unwrap();
+            char::from(b'0' + digit as u8)
+        })
+        .collect();
+
+    // E.g. "1299,-" or "12." at the end of a sentence
+    let token = token.trim_end_matches(['.', ',']).to_owned();
+
+    Some((token, if cents.is_empty() { None } else { Some(cents) }))
+}
+
+fn is_digit_group_at(chars: &[char], i: usize) -> bool {
+    let group_len = chars[i.min(chars.len())..]
+        .iter()
+        .take_while(|c| c.is_ascii_digit())
+        .count();
+    group_len == 3
+}
+
+fn digits_only(token: &str) -> String {
+    token.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+/// Turns the token into a number with `.` as the decimal separator and no grouping.
+fn normalize(token: &str, decimal_separator: Option<char>) -> Option<String> {
+    let token: String = token
+        .chars()
+        .filter(|c| !GROUPING_CHARS.contains(c))
+        .collect();
+
+    let decimal_separator = match decimal_separator {
+        Some(v) => Some(v),
+        None => guess_decimal_separator(&token),
+    };
+
+    let decimal_separator = match decimal_separator {
+        Some(v) => v,
+        None => return Some(digits_only(&token)),
+    };
+
+    let mut parts = token.split(decimal_separator);
+    let integer = parts.next().unwrap_or_default();
+    let fraction = parts.next();
+
+    // Decimal separator can't appear twice
+    if parts.next().is_some() {
+        return None;
+    }
+
+    match fraction {
+        Some(fraction) => Some(format!(
+            "{}.{}",
+            digits_only(integer),
+            digits_only(fraction)
+        )),
+        None => Some(digits_only(integer)),
+    }
+}
+
+fn guess_decimal_separator(token: &str) -> Option<char> {
+    let last_dot = token.rfind('.');
+    let last_comma = token.rfind(',');
+
+    let (separator, position) = match (last_dot, last_comma) {
+        (None, None) => return None,
+        (Some(dot), Some(comma)) => {
+            return Some(if dot > comma { '.' } else { ',' });
+        }
+        (Some(dot), None) => ('.', dot),
+        (None, Some(comma)) => (',', comma),
+    };
+
+    // Repeated separator only groups digits. E.g. "1.299.000"
+    if token.matches(separator).count() > 1 {
+        return None;
+    }
+
+    let integer = &token[..position];
+    let fraction = &token[position + 1..];
+
+    if fraction.len() == 3 && integer != "0" && !integer.is_empty() {
+        None
+    } else {
+        Some(separator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guessed_separators() {
+        let cases = [
+            ("1299", "1299"),
+            ("1299,00 zł", "1299.00"),
+            ("1 299,00 zł", "1299.00"),
+            ("1\u{a0}299,00\u{a0}zł", "1299.00"),
+            ("1\u{202f}299,00 zł", "1299.00"),
+            ("1.299,00 zł", "1299.00"),
+            ("1,299.00", "1299.00"),
+            ("1.299", "1299"),
+            ("1,299", "1299"),
+            ("1.299.000", "1299000"),
+            ("1,299,000.50", "1299000.50"),
+            ("12 345 678,90", "12345678.90"),
+            ("1'299.50 CHF", "1299.50"),
+            ("0.299", "0.299"),
+            ("12,5 zł", "12.5"),
+            ("12.99", "12.99"),
+            ("1299,-", "1299"),
+            ("1 299,- zł", "1299"),
+            ("Cena: 12.", "12"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(
+                parse_price(input, None).unwrap().to_string(),
+                expected,
+                "input: {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn currency_symbols_prefixes_and_ranges() {
+        let cases = [
+            ("$1,299.99", "1299.99"),
+            ("€ 15,50", "15.50"),
+            ("15,50 €", "15.50"),
+            ("PLN 99.90", "99.90"),
+            ("od 1 299 zł", "1299"),
+            ("from $19.99", "19.99"),
+            ("już od 49,99 zł", "49.99"),
+            ("100 - 200 zł", "100"),
+            ("100,00–200,00 zł", "100.00"),
+            ("1 099 zł - 1 299 zł", "1099"),
+            ("\n\t  2 499,00 zł \n", "2499.00"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(
+                parse_price(input, None).unwrap().to_string(),
+                expected,
+                "input: {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn superscript_cents() {
+        let cases = [
+            ("1299⁹⁹", "1299.99"),
+            ("1 299⁰⁰ zł", "1299.00"),
+            ("1.299⁵⁰", "1299.50"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(
+                parse_price(input, None).unwrap().to_string(),
+                expected,
+                "input: {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn configured_decimal_separator() {
+        let cases = [
+            ("1.299", Some(','), "1299"),
+            ("1.299", Some('.'), "1.299"),
+            ("1,299", Some(','), "1.299"),
+            ("1,299.00", Some('.'), "1299.00"),
+            ("1.299,00", Some(','), "1299.00"),
+            ("1 299", Some(','), "1299"),
+        ];
+
+        for (input, separator, expected) in cases {
+            assert_eq!(
+                parse_price(input, separator).unwrap().to_string(),
+                expected,
+                "input: {:?}, separator: {:?}",
+                input,
+                separator
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_prices() {
+        let cases = [
+            ("", None),
+            ("brak", None),
+            ("zł", None),
+            ("1,299,00", Some(',')),
+        ];
+
+        for (input, separator) in cases {
+            assert!(
+                parse_price(input, separator).is_err(),
+                "input: {:?}, separator: {:?}",
+                input,
+                separator
+            );
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use crate::config::{DomainRule, RedirectPolicy};
+
+/// Where the download landed, compared with the requested url.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Landing {
+    /// The requested page, maybe under a slightly different url
+    Requested,
+    /// Another url of the same product, the offer should use it from now on
+    Moved(String),
+    /// Home, category or any other page that isn't the product
+    Elsewhere,
+}
+
+/// `rule` is the rule of the requested url.
+pub fn classify(
+    requested: &url::Url,
+    landed: &url::Url,
+    html: &str,
+    rule: Option<&DomainRule>,
+    policy: &RedirectPolicy,
+) -> Landing {
+    let requested_key = normalize(requested, policy);
+    if normalize(landed, policy) == requested_key {
+        return Landing::Requested;
+    }
+
+    // Shops send products they don't sell anymore to the home page
+    if landed.path().trim_end_matches('/').is_empty() {
+        return Landing::Elsewhere;
+    }
+
+    if policy.follow_canonical {
+        if let Some(canonical) = canonical_url(html, landed) {
+            if normalize(&canonical, policy) == requested_key {
+                return Landing::Requested;
+            }
+        }
+    }
+
+    // The same path alone says nothing, query routed shops show every page under one path
+    // and another host may be another shop
+    let product_page = policy.follow_to_product_pages
+        && rule.is_some_and(|rule| !rule.path_prefix.is_empty() && rule.matches(landed));
+
+    if product_page {
+        Landing::Moved(clean(landed, policy).to_string())
+    } else {
+        Landing::Elsewhere
+    }
+}
+
+/// Host without "www.", path without the trailing slash and sorted query parameters which are not ignored.
+/// Scheme and fragment don't matter.
+fn normalize(url: &url::Url, policy: &RedirectPolicy) -> String {
+    let host = url.host_str().unwrap_or_default().to_lowercase();
+    let host = host.trim_start_matches("www.");
+    let port = url
+        .port()
+        .map(|port| format!(":{}", port))
+        .unwrap_or_default();
+
+    let mut query: Vec<(String, String)> = kept_query_pairs(url, policy);
+    query.sort();
+    let query: Vec<String> = query
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+
+    format!(
+        "{}{}{}?{}",
+        host,
+        port,
+        normalize_path(url),
+        query.join("&")
+    )
+}
+
+fn normalize_path(url: &url::Url) -> &str {
+    url.path().trim_end_matches('/')
+}
+
+/// Url without the fragment and ignored query parameters.
+fn clean(url: &url::Url, policy: &RedirectPolicy) -> url::Url {
+    let query = kept_query_pairs(url, policy);
+
+    let mut url = url.clone();
+    url.set_fragment(None);
+    if query.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(query);
+    }
+    url
+}
+
+fn kept_query_pairs(url: &url::Url, policy: &RedirectPolicy) -> Vec<(String, String)> {
+    url.query_pairs()
+        .filter(|(key, _)| !is_ignored(key, policy))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect()
+}
+
+fn is_ignored(key: &str, policy: &RedirectPolicy) -> bool {
+    policy
+        .ignored_query_params
+        .iter()
+        .any(|ignored| match ignored.strip_suffix('*') {
+            Some(prefix) => key.starts_with(prefix),
+            None => key == ignored,
+        })
+}
+
+/// Canonical link of the page, or its Open Graph url.
+fn canonical_url(html: &str, base: &url::Url) -> Option<url::Url> {
+    let document = scraper::Html::parse_document(html);
+
+    let selectors = [
+        ("link[rel=\"canonical\"]", "href"),
+        ("meta[property=\"og:url\"]", "content"),
+    ];
+
+    selectors.iter().find_map(|(selector, attribute)| {
+        let selector = scraper::Selector::parse(selector).ok()?;
+        let href = document.select(&selector).next()?.value().attr(attribute)?;
+        base.join(href.trim()).ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> url::Url {
+        url::Url::parse(url).unwrap()
+    }
+
+    fn x_kom() -> DomainRule {
+        DomainRule {
+            host: "x-kom.pl".to_owned(),
+            path_prefix: "/p/".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn small_url_differences_are_the_same_page() {
+        let landing = classify(
+            &url("http://x-kom.pl/p/123-karta?utm_source=ceneo"),
+            &url("https://www.x-kom.pl/p/123-karta/#opis"),
+            "",
+            Some(&x_kom()),
+            &RedirectPolicy::default(),
+        );
+        assert_eq!(landing, Landing::Requested);
+    }
+
+    #[test]
+    fn product_moved_to_another_url() {
+        let requested = url("https://www.x-kom.pl/p/123-karta-rtx");
+        let policy = RedirectPolicy::default();
+
+        let landing = classify(
+            &requested,
+            &url("https://www.x-kom.pl/p/123-karta-graficzna-rtx?utm_medium=redirect"),
+            "",
+            Some(&x_kom()),
+            &policy,
+        );
+        assert_eq!(
+            landing,
+            Landing::Moved("https://www.x-kom.pl/p/123-karta-graficzna-rtx".to_owned())
+        );
+
+        let landing = classify(
+            &requested,
+            &url("https://www.x-kom.pl/g-5/c/345-karty-graficzne.html"),
+            "",
+            Some(&x_kom()),
+            &policy,
+        );
+        assert_eq!(landing, Landing::Elsewhere);
+
+        let landing = classify(
+            &requested,
+            &url("https://www.x-kom.pl/"),
+            "",
+            Some(&x_kom()),
+            &policy,
+        );
+        assert_eq!(landing, Landing::Elsewhere);
+    }
+
+    #[test]
+    fn canonical_link_points_to_the_requested_page() {
+        let html = r#"<html><head><link rel="canonical" href="/produkt/123"></head></html>"#;
+
+        let landing = classify(
+            &url("https://shop.pl/produkt/123"),
+            &url("https://shop.pl/index.php?route=product&id=123"),
+            html,
+            None,
+            &RedirectPolicy::default(),
+        );
+        assert_eq!(landing, Landing::Requested);
+
+        let landing = classify(
+            &url("https://shop.pl/produkt/123"),
+            &url("https://shop.pl/index.php?route=product&id=123"),
+            html,
+            None,
+            &RedirectPolicy {
+                follow_canonical: false,
+                ..Default::default()
+            },
+        );
+        assert_eq!(landing, Landing::Elsewhere);
+    }
+
+    #[test]
+    fn query_routed_shop_redirects_to_a_category() {
+        let landing = classify(
+            &url("https://sklep.pl/index.php?id_product=1&controller=product"),
+            &url("https://sklep.pl/index.php?controller=category&id=5"),
+            "",
+            None,
+            &RedirectPolicy::default(),
+        );
+        assert_eq!(landing, Landing::Elsewhere);
+    }
+
+    #[test]
+    fn redirect_to_another_host_is_elsewhere() {
+        let landing = classify(
+            &url("https://www.x-kom.pl/p/123-karta"),
+            &url("https://www.al.to/p/123-karta"),
+            "",
+            Some(&x_kom()),
+            &RedirectPolicy::default(),
+        );
+        assert_eq!(landing, Landing::Elsewhere);
+    }
+}
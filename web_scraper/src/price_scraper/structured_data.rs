@@ -0,0 +1,363 @@
+use database::decimal::Decimal;
+use database::models::price::Availability;
+use scraper::{ElementRef, Html, Selector};
+use serde_json::Value;
+use std::str::FromStr;
+
+use super::price_parser::parse_price;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct StructuredOffer {
+    pub price: Option<Decimal>,
+    pub availability: Option<Availability>,
+    /// ISO 4217 code. E.g. "PLN"
+    pub currency: Option<String>,
+}
+
+/// Looks for the offer in the machine readable metadata of the page.
+/// Sources are checked in order: JSON-LD (schema.org Product/Offer),
+/// OpenGraph product tags and finally microdata `itemprop`s.
+pub fn find_offer(document: &Html) -> StructuredOffer {
+    let json_ld = json_ld_offer(document);
+
+    StructuredOffer {
+        price: json_ld
+            .price
+            .or_else(|| open_graph_price(document))
+            .or_else(|| microdata_price(document)),
+        availability: json_ld
+            .availability
+            .or_else(|| open_graph_availability(document))
+            .or_else(|| microdata_availability(document)),
+        currency: json_ld
+            .currency
+            .or_else(|| open_graph_currency(document))
+            .or_else(|| microdata_currency(document)),
+    }
+}
+
+/// Understands schema.org urls (`https://schema.org/OutOfStock`), their short forms
+/// and OpenGraph values (`instock`, `oos`, `out of stock`).
+pub fn parse_availability(s: &str) -> Option<Availability> {
+    let name = s
+        .trim()
+        .rsplit('/')
+        .next()?
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+
+    match name.as_str() {
+        "instock"
+        | "limitedavailability"
+        | "onlineonly"
+        | "preorder"
+        | "presale"
+        | "madetoorder"
+        | "availablefororder" => Some(Availability::Available),
+        "backorder" => Some(Availability::TemporarilyUnavailable),
+        "outofstock" | "oos" | "soldout" | "discontinued" | "instoreonly" => {
+            Some(Availability::Unavailable)
+        }
+        _ => None,
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// JSON-LD
+
+fn json_ld_offer(document: &Html) -> StructuredOffer {
+    let selector = Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+
+    let blocks: Vec<Value> = document
+        .select(&selector)
+        .flat_map(|el| serde_json::from_str::<Value>(&el.text().collect::<String>()))
+        .collect();
+
+    // Prefer offers attached to a product, they describe the page itself
+    let products = blocks.iter().flat_map(|v| nodes_of_type(v, "Product"));
+    for product in products {
+        if let Some(offer) = product.get("offers").and_then(read_offer) {
+            return offer;
+        }
+    }
+
+    // Some shops put a bare Offer on the page
+    blocks
+        .iter()
+        .flat_map(|v| nodes_of_type(v, "Offer"))
+        .find_map(read_offer)
+        .unwrap_or_default()
+}
+
+/// Returns top level nodes of the given schema.org type, looking through arrays and `@graph`.
+fn nodes_of_type<'a>(value: &'a Value, schema_type: &str) -> Vec<&'a Value> {
+    match value {
+        Value::Array(array) => array
+            .iter()
+            .flat_map(|v| nodes_of_type(v, schema_type))
+            .collect(),
+        Value::Object(object) => {
+            if let Some(graph) = object.get("@graph") {
+                return nodes_of_type(graph, schema_type);
+            }
+            if is_of_type(value, schema_type) {
+                vec![value]
+            } else {
+                vec![]
+            }
+        }
+        _ => vec![],
+    }
+}
+
+fn is_of_type(value: &Value, schema_type: &str) -> bool {
+    let matches = |t: &str| t == schema_type || t.ends_with(&format!("/{}", schema_type));
+    match value.get("@type") {
+        Some(Value::String(t)) => matches(t),
+        Some(Value::Array(types)) => types.iter().flat_map(Value::as_str).any(matches),
+        _ => false,
+    }
+}
+
+/// `offers` can be a single Offer, an AggregateOffer or an array of them.
+fn read_offer(offers: &Value) -> Option<StructuredOffer> {
+    match offers {
+        Value::Array(array) => array.iter().find_map(read_offer),
+        Value::Object(_) => {
+            let offer = StructuredOffer {
+                price: offers
+                    .get("price")
+                    .or_else(|| offers.get("lowPrice"))
+                    .or_else(|| {
+                        offers
+                            .get("priceSpecification")
+                            .and_then(|v| v.get("price"))
+                    })
+                    .and_then(value_to_decimal),
+                availability: offers
+                    .get("availability")
+                    .and_then(Value::as_str)
+                    .and_then(parse_availability),
+                currency: offers
+                    .get("priceCurrency")
+                    .or_else(|| {
+                        offers
+                            .get("priceSpecification")
+                            .and_then(|v| v.get("priceCurrency"))
+                    })
+                    .and_then(Value::as_str)
+                    .and_then(parse_currency),
+            };
+
+            if offer == StructuredOffer::default() {
+                None
+            } else {
+                Some(offer)
+            }
+        }
+        _ => None,
+    }
+}
+
+fn value_to_decimal(value: &Value) -> Option<Decimal> {
+    match value {
+        Value::Number(number) => Decimal::from_str(&number.to_string()).ok(),
+        // Schema.org requires '.' as the decimal separator
+        Value::String(s) => parse_price(s, Some('.')).ok(),
+        _ => None,
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// OpenGraph
+
+fn open_graph_price(document: &Html) -> Option<Decimal> {
+    let selector = Selector::parse(
+        r#"meta[property="product:price:amount"], meta[property="og:price:amount"]"#,
+    )
+    .unwrap();
+
+    document
+        .select(&selector)
+        .flat_map(|el| el.value().attr("content"))
+        .find_map(|s| parse_price(s, Some('.')).ok())
+}
+
+fn open_graph_currency(document: &Html) -> Option<String> {
+    let selector = Selector::parse(
+        r#"meta[property="product:price:currency"], meta[property="og:price:currency"]"#,
+    )
+    .unwrap();
+
+    document
+        .select(&selector)
+        .flat_map(|el| el.value().attr("content"))
+        .find_map(parse_currency)
+}
+
+fn open_graph_availability(document: &Html) -> Option<Availability> {
+    let selector = Selector::parse(
+        r#"meta[property="product:availability"], meta[property="og:availability"]"#,
+    )
+    .unwrap();
+
+    document
+        .select(&selector)
+        .flat_map(|el| el.value().attr("content"))
+        .find_map(parse_availability)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Microdata
+
+fn microdata_price(document: &Html) -> Option<Decimal> {
+    let selector = Selector::parse(r#"[itemprop="price"]"#).unwrap();
+
+    document
+        .select(&selector)
+        .find_map(|el| match el.value().attr("content") {
+            // Machine readable value uses '.' as the decimal separator
+            Some(content) => parse_price(content, Some('.')).ok(),
+            None => parse_price(&el.text().collect::<String>(), None).ok(),
+        })
+}
+
+fn microdata_currency(document: &Html) -> Option<String> {
+    let selector = Selector::parse(r#"[itemprop="priceCurrency"]"#).unwrap();
+
+    document
+        .select(&selector)
+        .map(content_or_text)
+        .find_map(|s| parse_currency(&s))
+}
+
+fn microdata_availability(document: &Html) -> Option<Availability> {
+    let selector = Selector::parse(r#"[itemprop="availability"]"#).unwrap();
+
+    document
+        .select(&selector)
+        .map(|el| match el.value().attr("href") {
+            Some(v) => v.to_owned(),
+            None => content_or_text(el),
+        })
+        .find_map(|s| parse_availability(&s))
+}
+
+fn parse_currency(s: &str) -> Option<String> {
+    let s = s.trim();
+    if s.len() == 3 && s.chars().all(|c| c.is_ascii_alphabetic()) {
+        Some(s.to_uppercase())
+    } else {
+        None
+    }
+}
+
+fn content_or_text(el: ElementRef) -> String {
+    match el.value().attr("content") {
+        Some(v) => v.to_owned(),
+        None => el.text().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price_of(html: &str) -> Option<String> {
+        find_offer(&Html::parse_document(html))
+            .price
+            .map(|v| v.to_string())
+    }
+
+    fn availability_of(html: &str) -> Option<Availability> {
+        find_offer(&Html::parse_document(html)).availability
+    }
+
+    #[test]
+    fn json_ld_product_with_offer() {
+        let html = r#"<script type="application/ld+json">
+            {"@context": "https://schema.org", "@type": "Product", "name": "GPU",
+             "offers": {"@type": "Offer", "price": "1299.00", "priceCurrency": "PLN"}}
+        </script>"#;
+        assert_eq!(price_of(html).as_deref(), Some("1299.00"));
+        assert_eq!(
+            find_offer(&Html::parse_document(html)).currency.as_deref(),
+            Some("PLN")
+        );
+    }
+
+    #[test]
+    fn json_ld_graph_with_aggregate_offer() {
+        let html = r#"<script type="application/ld+json">
+            {"@graph": [
+                {"@type": "BreadcrumbList"},
+                {"@type": ["Product"], "offers": [{"@type": "AggregateOffer", "lowPrice": 49.99}]}
+            ]}
+        </script>"#;
+        assert_eq!(price_of(html).as_deref(), Some("49.99"));
+    }
+
+    #[test]
+    fn invalid_json_ld_falls_back_to_open_graph() {
+        let html = r#"<head>
+            <script type="application/ld+json">{ not json </script>
+            <meta property="product:price:amount" content="15.50">
+            <meta property="product:price:currency" content="eur">
+        </head>"#;
+        assert_eq!(price_of(html).as_deref(), Some("15.50"));
+        assert_eq!(
+            find_offer(&Html::parse_document(html)).currency.as_deref(),
+            Some("EUR")
+        );
+    }
+
+    #[test]
+    fn microdata_content_attribute_and_text() {
+        let html = r#"<span itemprop="price" content="199.90">199,90 zł</span>"#;
+        assert_eq!(price_of(html).as_deref(), Some("199.90"));
+
+        let html = r#"<span itemprop="price">24,99</span>"#;
+        assert_eq!(price_of(html).as_deref(), Some("24.99"));
+    }
+
+    #[test]
+    fn no_structured_data() {
+        assert_eq!(price_of("<div class='price'>10 zł</div>"), None);
+        assert_eq!(availability_of("<div class='price'>10 zł</div>"), None);
+    }
+
+    #[test]
+    fn json_ld_availability_without_price() {
+        let html = r#"<script type="application/ld+json">
+            {"@type": "Product", "offers": {"@type": "Offer", "availability": "https://schema.org/OutOfStock"}}
+        </script>"#;
+        assert_eq!(price_of(html), None);
+        assert_eq!(availability_of(html), Some(Availability::Unavailable));
+    }
+
+    #[test]
+    fn open_graph_and_microdata_availability() {
+        let html = r#"<meta property="product:availability" content="out of stock">"#;
+        assert_eq!(availability_of(html), Some(Availability::Unavailable));
+
+        let html = r#"<link itemprop="availability" href="http://schema.org/BackOrder">"#;
+        assert_eq!(
+            availability_of(html),
+            Some(Availability::TemporarilyUnavailable)
+        );
+    }
+
+    #[test]
+    fn availability_names() {
+        assert_eq!(parse_availability("InStock"), Some(Availability::Available));
+        assert_eq!(parse_availability("instock"), Some(Availability::Available));
+        assert_eq!(parse_availability("oos"), Some(Availability::Unavailable));
+        assert_eq!(
+            parse_availability("https://schema.org/SoldOut"),
+            Some(Availability::Unavailable)
+        );
+        assert_eq!(parse_availability("something else"), None);
+    }
+}
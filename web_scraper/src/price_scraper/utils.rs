@@ -1,22 +1,46 @@
-use std::num::ParseFloatError;
+use bigdecimal::BigDecimal;
+use database::decimal::Decimal;
 
-pub fn string_to_float(s: &str) -> error_stack::Result<f64, ParseFloatError> {
-    let mut first_dot = true;
-    s.chars()
-        .into_iter()
-        .map(|c| if c == ',' { '.' } else { c })
-        // TODO: Try doing it with Iterator::scan()
-        .filter(|&c| {
-            let first_occurence_of_dot = c == '.' && first_dot;
-            if first_occurence_of_dot {
-                first_dot = false;
-            }
-            c.is_numeric() || first_occurence_of_dot
-        })
-        .collect::<String>()
-        .parse::<f64>()
-        .map_err(|error| {
-            error_stack::report!(error)
-                .attach_printable(format!("Cannot parse string to float. String: {}", s))
-        })
+/// True when the new price differs from the last one by `percent`% or more.
+pub fn is_suspicious_change(last_price: &Decimal, new_price: &Decimal, percent: u32) -> bool {
+    (&new_price.0 - &last_price.0).abs() * BigDecimal::from(100)
+        >= last_price.0.abs() * BigDecimal::from(percent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn suspicious_change_is_exact() {
+        assert!(!is_suspicious_change(
+            &decimal("100.00"),
+            &decimal("90.01"),
+            10
+        ));
+        assert!(is_suspicious_change(
+            &decimal("100.00"),
+            &decimal("90.00"),
+            10
+        ));
+        assert!(is_suspicious_change(
+            &decimal("100.00"),
+            &decimal("110.00"),
+            10
+        ));
+        assert!(!is_suspicious_change(
+            &decimal("1299.99"),
+            &decimal("1299.98"),
+            10
+        ));
+        assert!(is_suspicious_change(
+            &decimal("100.00"),
+            &decimal("95.00"),
+            5
+        ));
+    }
 }
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use rand::Rng;
+
+use crate::config::{BrowserProfile, PriceScraperConfig, ProfileRotation};
+
+/// Picks the identity every download is made with. Profiles are referred to by their index.
+pub struct ProfilePool {
+    profiles: Vec<BrowserProfile>,
+    rotation: ProfileRotation,
+    /// Profile chosen for every shop, when it's sticky
+    by_domain: Mutex<HashMap<String, usize>>,
+}
+
+impl ProfilePool {
+    pub fn new(config: &PriceScraperConfig) -> Self {
+        let profiles = if config.profiles.is_empty() {
+            vec![BrowserProfile {
+                user_agent: config.user_agent.clone(),
+                headers: HashMap::new(),
+            }]
+        } else {
+            config.profiles.clone()
+        };
+
+        Self {
+            profiles,
+            rotation: config.profile_rotation,
+            by_domain: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn rotation(&self) -> ProfileRotation {
+        self.rotation
+    }
+
+    /// Profile for a download from the shop, `domain_key` tells shops apart.
+    pub fn choose(&self, domain_key: &str) -> usize {
+        match self.rotation {
+            ProfileRotation::PerRequest => self.random(),
+            ProfileRotation::PerDomain => *self
+                .by_domain
+                .lock()
+                .unwrap()
+                .entry(domain_key.to_owned())
+                .or_insert_with(|| self.random()),
+        }
+    }
+
+    pub fn random(&self) -> usize {
+        rand::thread_rng().gen_range(0..self.profiles.len())
+    }
+
+    pub fn get(&self, index: usize) -> &BrowserProfile {
+        &self.profiles[index]
+    }
+
+    pub fn user_agents(&self) -> Vec<String> {
+        self.profiles
+            .iter()
+            .map(|profile| profile.user_agent.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(rotation: ProfileRotation) -> ProfilePool {
+        ProfilePool {
+            profiles: (0..8)
+                .map(|i| BrowserProfile {
+                    user_agent: format!("agent {}", i),
+                    headers: HashMap::new(),
+                })
+                .collect(),
+            rotation,
+            by_domain: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[test]
+    fn shop_keeps_its_profile() {
+        let pool = pool(ProfileRotation::PerDomain);
+
+        let chosen = pool.choose("x-kom.pl");
+        assert!((0..20).all(|_| pool.choose("x-kom.pl") == chosen));
+    }
+
+    #[test]
+    fn profiles_rotate_per_request() {
+        let pool = pool(ProfileRotation::PerRequest);
+
+        let chosen = pool.choose("x-kom.pl");
+        assert!((0..50).any(|_| pool.choose("x-kom.pl") != chosen));
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::OnceCell;
+
+use crate::config::{DomainRule, RobotsConfig};
+use crate::scheduler::Scheduler;
+
+/// Rules of robots.txt that apply to our user agent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RobotsRules {
+    /// Allow or disallow, with the path pattern
+    rules: Vec<(bool, String)>,
+    pub crawl_delay: Option<Duration>,
+}
+
+struct Group {
+    user_agents: Vec<String>,
+    rules: Vec<(bool, String)>,
+    crawl_delay: Option<Duration>,
+}
+
+impl RobotsRules {
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    pub fn disallow_all() -> Self {
+        Self {
+            rules: vec![(false, "/".to_owned())],
+            crawl_delay: None,
+        }
+    }
+
+    /// Takes groups of the most specific user agent token found in our user agent,
+    /// `*` is used when none of them is.
+    pub fn parse(text: &str, user_agent: &str) -> Self {
+        let user_agent = user_agent.to_lowercase();
+
+        let mut groups: Vec<Group> = Vec::new();
+        let mut reading_user_agents = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+                None => continue,
+            };
+
+            if key == "user-agent" {
+                // User agents listed one after another share the group
+                if !reading_user_agents {
+                    groups.push(Group {
+                        user_agents: Vec::new(),
+                        rules: Vec::new(),
+                        crawl_delay: None,
+                    });
+                    reading_user_agents = true;
+                }
+                if let Some(group) = groups.last_mut() {
+                    group.user_agents.push(value.to_lowercase());
+                }
+                continue;
+            }
+
+            reading_user_agents = false;
+            let group = match groups.last_mut() {
+                Some(v) => v,
+                // Rules before any user agent don't belong to anyone
+                None => continue,
+            };
+
+            match key.as_str() {
+                // Empty disallow allows everything
+                "disallow" if !value.is_empty() => group.rules.push((false, value.to_owned())),
+                "allow" if !value.is_empty() => group.rules.push((true, value.to_owned())),
+                "crawl-delay" => {
+                    group.crawl_delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|v| v.is_finite() && *v >= 0.0)
+                        .map(Duration::from_secs_f64)
+                }
+                _ => {}
+            }
+        }
+
+        let specificity = |group: &Group| {
+            group
+                .user_agents
+                .iter()
+                .filter_map(|token| match token.as_str() {
+                    "*" => Some(0),
+                    token if user_agent.contains(token) => Some(token.len()),
+                    _ => None,
+                })
+                .max()
+        };
+
+        let best = match groups.iter().filter_map(specificity).max() {
+            Some(v) => v,
+            None => return Self::allow_all(),
+        };
+
+        // Groups of the same user agent are merged
+        groups
+            .into_iter()
+            .filter(|group| specificity(group) == Some(best))
+            .fold(Self::allow_all(), |mut rules, group| {
+                rules.rules.extend(group.rules);
+                rules.crawl_delay = rules.crawl_delay.max(group.crawl_delay);
+                rules
+            })
+    }
+
+    /// The longest matching pattern decides, allow wins ties. `path` may contain the query.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| pattern_matches(pattern, path))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .is_none_or(|(allow, _)| *allow)
+    }
+}
+
+/// Pattern matches the beginning of the path, `*` matches any characters, `$` anchors the end.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(v) => (v, true),
+        None => (pattern, false),
+    };
+
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match path.strip_prefix(first) {
+        Some(v) => v,
+        None => return false,
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        let is_last = i == parts.len() - 1;
+        if is_last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+
+    !anchored || rest.is_empty()
+}
+
+/// Rules for every user agent the scraper sends. Downloads rotate the profiles,
+/// so a url is allowed only when robots.txt allows it to all of them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RobotsPolicy {
+    rules: Vec<RobotsRules>,
+}
+
+impl RobotsPolicy {
+    pub fn parse(text: &str, user_agents: &[String]) -> Self {
+        Self {
+            rules: user_agents
+                .iter()
+                .map(|user_agent| RobotsRules::parse(text, user_agent))
+                .collect(),
+        }
+    }
+
+    pub fn is_allowed(&self, path: &str) -> bool {
+        self.rules.iter().all(|rules| rules.is_allowed(path))
+    }
+
+    /// The longest delay asked from any of the user agents.
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.rules
+            .iter()
+            .filter_map(|rules| rules.crawl_delay)
+            .max()
+    }
+}
+
+impl From<RobotsRules> for RobotsPolicy {
+    fn from(rules: RobotsRules) -> Self {
+        Self { rules: vec![rules] }
+    }
+}
+
+struct CachedRules {
+    rules: Arc<RobotsPolicy>,
+    expires_at: Instant,
+}
+
+/// Robots rules of every origin are downloaded once and kept until they expire,
+/// see `RobotsConfig`.
+pub struct RobotsCache {
+    /// User agents of all profiles
+    user_agents: Vec<String>,
+    cache_time: Duration,
+    failure_cache_time: Duration,
+    origins: Mutex<HashMap<String, Arc<OnceCell<CachedRules>>>>,
+}
+
+impl RobotsCache {
+    pub fn new(user_agents: Vec<String>, config: &RobotsConfig) -> Self {
+        Self {
+            user_agents,
+            cache_time: Duration::from_secs(config.cache_secs),
+            failure_cache_time: Duration::from_secs(config.failure_cache_secs),
+            origins: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Robots.txt is downloaded in a slot of the shop from the scheduler, like its pages.
+    pub async fn rules(
+        &self,
+        client: &reqwest::Client,
+        scheduler: &Scheduler,
+        rule: &DomainRule,
+        url: &url::Url,
+    ) -> Arc<RobotsPolicy> {
+        let cell = self.cell(url.origin().ascii_serialization());
+
+        // Pages of the same shop downloaded at the same time wait for one robots.txt download
+        let cached = cell
+            .get_or_init(|| async {
+                let _slot = scheduler.acquire(rule, url.as_str()).await;
+                let (rules, failed) = self.download(client, url).await;
+                let cache_time = if failed {
+                    self.failure_cache_time
+                } else {
+                    self.cache_time
+                };
+                CachedRules {
+                    rules: Arc::new(rules),
+                    expires_at: Instant::now() + cache_time,
+                }
+            })
+            .await;
+
+        Arc::clone(&cached.rules)
+    }
+
+    /// Expired rules are replaced with an empty cell, so the next caller downloads them again.
+    fn cell(&self, origin: String) -> Arc<OnceCell<CachedRules>> {
+        let mut origins = self.origins.lock().unwrap();
+        let cell = origins.entry(origin).or_default();
+        if cell
+            .get()
+            .is_some_and(|cached| cached.expires_at <= Instant::now())
+        {
+            *cell = Arc::default();
+        }
+        Arc::clone(cell)
+    }
+
+    /// Missing robots.txt allows everything, and when the server fails nothing is allowed.
+    /// Network errors allow everything, downloading the page will fail on its own anyway.
+    /// The flag tells whether the server or the network failed.
+    async fn download(&self, client: &reqwest::Client, url: &url::Url) -> (RobotsPolicy, bool) {
+        let robots_url = match url.join("/robots.txt") {
+            Ok(v) => v,
+            Err(_) => return (RobotsRules::allow_all().into(), false),
+        };
+
+        let response = match client.get(robots_url.clone()).send().await {
+            Ok(v) => v,
+            Err(error) => {
+                log::warn!(
+                    "Couldn't download robots.txt. Url: {}. Cause: {:?}",
+                    robots_url,
+                    error
+                );
+                return (RobotsRules::allow_all().into(), true);
+            }
+        };
+
+        let status = response.status();
+        if status.is_server_error() {
+            log::warn!(
+                "Server failed to return robots.txt, nothing will be downloaded. Url: {}. Status: {}",
+                robots_url,
+                status
+            );
+            return (RobotsRules::disallow_all().into(), true);
+        }
+        if !status.is_success() {
+            return (RobotsRules::allow_all().into(), false);
+        }
+
+        match response.text().await {
+            Ok(text) => (RobotsPolicy::parse(&text, &self.user_agents), false),
+            Err(error) => {
+                log::warn!(
+                    "Couldn't read robots.txt. Url: {}. Cause: {:?}",
+                    robots_url,
+                    error
+                );
+                (RobotsRules::allow_all().into(), true)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) Chrome/103.0.0.0";
+
+    #[test]
+    fn most_specific_group_applies() {
+        let text = "
+User-agent: Googlebot
+Disallow: /
+
+User-agent: *
+Disallow: /koszyk
+Allow: /koszyk/promocje
+Crawl-delay: 2.5 # seconds
+
+User-agent: chrome
+User-agent: firefox
+Disallow: /szukaj
+";
+        let rules = RobotsRules::parse(text, USER_AGENT);
+
+        assert!(!rules.is_allowed("/szukaj?q=gpu"));
+        assert!(rules.is_allowed("/koszyk"));
+        assert_eq!(rules.crawl_delay, None);
+
+        let rules = RobotsRules::parse(text, "r-prices");
+        assert!(!rules.is_allowed("/koszyk/1"));
+        assert!(rules.is_allowed("/koszyk/promocje"));
+        assert!(rules.is_allowed("/p/123"));
+        assert_eq!(rules.crawl_delay, Some(Duration::from_millis(2500)));
+    }
+
+    #[test]
+    fn wildcards_and_anchors() {
+        let rules = RobotsRules::parse(
+            "User-agent: *\nDisallow: /*?sort=\nDisallow: /*.pdf$\nDisallow:\n",
+            USER_AGENT,
+        );
+
+        assert!(!rules.is_allowed("/laptopy?sort=price"));
+        assert!(!rules.is_allowed("/files/manual.pdf"));
+        assert!(rules.is_allowed("/files/manual.pdf.html"));
+        assert!(rules.is_allowed("/laptopy"));
+    }
+
+    #[test]
+    fn no_matching_group_allows_everything() {
+        let rules = RobotsRules::parse("User-agent: Googlebot\nDisallow: /\n", USER_AGENT);
+        assert!(rules.is_allowed("/p/123"));
+    }
+
+    #[test]
+    fn every_profile_has_to_be_allowed() {
+        let text = "User-agent: firefox\nDisallow: /szukaj\nCrawl-delay: 5\n\nUser-agent: *\nDisallow: /koszyk\n";
+        let user_agents = vec![
+            USER_AGENT.to_owned(),
+            "Mozilla/5.0 (X11; Linux x86_64; rv:102.0) Firefox/102.0".to_owned(),
+        ];
+        let policy = RobotsPolicy::parse(text, &user_agents);
+
+        assert!(!policy.is_allowed("/szukaj?q=gpu"));
+        assert!(!policy.is_allowed("/koszyk"));
+        assert!(policy.is_allowed("/p/123"));
+        assert_eq!(policy.crawl_delay(), Some(Duration::from_secs(5)));
+    }
+
+    #[tokio::test]
+    async fn expired_rules_are_downloaded_again() {
+        let cache = RobotsCache::new(vec![USER_AGENT.to_owned()], &RobotsConfig::default());
+        let origin = "https://www.x-kom.pl".to_owned();
+
+        let cell = cache.cell(origin.clone());
+        cell.get_or_init(|| async {
+            CachedRules {
+                rules: Arc::new(RobotsRules::disallow_all().into()),
+                expires_at: Instant::now() + Duration::from_secs(600),
+            }
+        })
+        .await;
+        assert!(Arc::ptr_eq(&cell, &cache.cell(origin.clone())));
+
+        let expired = Arc::new(OnceCell::new_with(Some(CachedRules {
+            rules: Arc::new(RobotsRules::disallow_all().into()),
+            expires_at: Instant::now(),
+        })));
+        cache
+            .origins
+            .lock()
+            .unwrap()
+            .insert(origin.clone(), expired);
+        assert!(cache.cell(origin).get().is_none());
+    }
+}
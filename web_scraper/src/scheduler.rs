@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{sleep_until, Duration, Instant};
+
+use crate::config::{DomainRule, SchedulerConfig};
+
+/// Decides when a download may start. Downloads from one shop are limited
+/// by their own concurrency and spread out in time, and all of them share the global limit.
+pub struct Scheduler {
+    config: SchedulerConfig,
+    global: Arc<Semaphore>,
+    domains: Mutex<HashMap<String, Arc<DomainState>>>,
+}
+
+struct DomainState {
+    permits: Arc<Semaphore>,
+    timing: Mutex<Timing>,
+}
+
+struct Timing {
+    min_delay: Duration,
+    /// The earliest moment the next download may start
+    next_start: Instant,
+}
+
+/// Download may run as long as the slot is held.
+pub struct Slot {
+    _domain: OwnedSemaphorePermit,
+    _global: OwnedSemaphorePermit,
+}
+
+impl Scheduler {
+    pub fn new(config: SchedulerConfig) -> Self {
+        Self {
+            global: Arc::new(Semaphore::new(config.max_concurrency.max(1))),
+            domains: Mutex::new(HashMap::new()),
+            config,
+        }
+    }
+
+    /// Waits until a download of the url is allowed.
+    /// Pages of the same rule share limits, pages without a rule are grouped by host.
+    pub async fn acquire(&self, rule: &DomainRule, url: &str) -> Slot {
+        let domain = self.domain_state(rule, url);
+
+        let domain_permit = Arc::clone(&domain.permits)
+            .acquire_owned()
+            .await
+            .expect("Semaphores of the scheduler are never closed");
+
+        // Reserve the start time, so downloads waiting at the same time keep the delay between them too
+        let start = {
+            let mut timing = domain.timing.lock().unwrap();
+            let start = timing.next_start.max(Instant::now());
+            timing.next_start = start + timing.min_delay;
+            start
+        };
+        sleep_until(start).await;
+
+        let global_permit = Arc::clone(&self.global)
+            .acquire_owned()
+            .await
+            .expect("Semaphores of the scheduler are never closed");
+
+        Slot {
+            _domain: domain_permit,
+            _global: global_permit,
+        }
+    }
+
+    /// Crawl-delay asked by the shop is kept when it's longer than the configured delay.
+    pub fn respect_crawl_delay(&self, rule: &DomainRule, url: &str, delay: Duration) {
+        let domain = self.domain_state(rule, url);
+        let mut timing = domain.timing.lock().unwrap();
+        timing.min_delay = timing.min_delay.max(delay);
+    }
+
+    /// Nothing is downloaded from the shop for the given time, e.g. when it asked to slow down.
+    pub fn hold_off(&self, rule: &DomainRule, url: &str, delay: Duration) {
+        let domain = self.domain_state(rule, url);
+        let mut timing = domain.timing.lock().unwrap();
+        timing.next_start = timing.next_start.max(Instant::now() + delay);
+    }
+
+    fn domain_state(&self, rule: &DomainRule, url: &str) -> Arc<DomainState> {
+        let key = rule.domain_key(url);
+
+        let mut domains = self.domains.lock().unwrap();
+        let state = domains.entry(key).or_insert_with(|| {
+            let max_concurrency = rule
+                .max_concurrency
+                .unwrap_or(self.config.max_concurrency_per_domain);
+            let min_delay_ms = rule
+                .min_delay_ms
+                .unwrap_or(self.config.min_delay_per_domain_ms);
+
+            Arc::new(DomainState {
+                permits: Arc::new(Semaphore::new(max_concurrency.max(1))),
+                timing: Mutex::new(Timing {
+                    min_delay: Duration::from_millis(min_delay_ms),
+                    next_start: Instant::now(),
+                }),
+            })
+        });
+
+        Arc::clone(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler() -> Scheduler {
+        Scheduler::new(SchedulerConfig {
+            max_concurrency: 8,
+            max_concurrency_per_domain: 1,
+            min_delay_per_domain_ms: 50,
+        })
+    }
+
+    #[tokio::test]
+    async fn downloads_from_one_shop_are_spread_out() {
+        let scheduler = scheduler();
+        let timer = std::time::Instant::now();
+
+        for _ in 0..3 {
+            let _slot = scheduler
+                .acquire(&DomainRule::default(), "https://shop.pl/p/1")
+                .await;
+        }
+
+        assert!(timer.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn different_shops_dont_wait_for_each_other() {
+        let scheduler = scheduler();
+        let timer = std::time::Instant::now();
+
+        let _first = scheduler
+            .acquire(&DomainRule::default(), "https://shop.pl/p/1")
+            .await;
+        let _second = scheduler
+            .acquire(&DomainRule::default(), "https://other-shop.pl/p/1")
+            .await;
+
+        assert!(timer.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn shop_can_ask_for_a_break() {
+        let scheduler = scheduler();
+        let rule = DomainRule::default();
+        let timer = std::time::Instant::now();
+
+        scheduler.hold_off(&rule, "https://shop.pl/p/1", Duration::from_millis(100));
+        let _slot = scheduler.acquire(&rule, "https://shop.pl/p/2").await;
+
+        assert!(timer.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn rule_overrides_limits() {
+        let scheduler = scheduler();
+        let rule = DomainRule {
+            host: "shop.pl".to_owned(),
+            max_concurrency: Some(2),
+            min_delay_ms: Some(0),
+            ..Default::default()
+        };
+
+        // Subdomains share the slots of the rule
+        let _first = scheduler.acquire(&rule, "https://shop.pl/p/1").await;
+        let second = tokio::time::timeout(
+            Duration::from_millis(50),
+            scheduler.acquire(&rule, "https://www.shop.pl/p/2"),
+        )
+        .await;
+        assert!(second.is_ok());
+
+        let third = tokio::time::timeout(
+            Duration::from_millis(50),
+            scheduler.acquire(&rule, "https://shop.pl/p/3"),
+        )
+        .await;
+        assert!(third.is_err());
+    }
+}
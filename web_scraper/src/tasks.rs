@@ -1,14 +1,20 @@
+use database::db::PostgresPool;
+use database::decimal::Decimal;
 use database::models::offer::Offer;
 use database::models::price::{Availability, CreatePriceInput, Price};
 use database::models::product::Product;
 use diesel::PgConnection;
-use itertools::izip;
 use log::{debug, error, info};
-use std::cell::RefCell;
 use std::fmt::{write, Display};
-use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tokio::time::sleep;
 
+use crate::config::PriceScraperConfig;
 use crate::email::email_many;
+use crate::offer_schedule::{next_interval, CheckOutcome};
 use crate::price_scraper::{GetPriceError, PriceScraper};
 
 ///////////////////////////////////////////////////////////////////////////////
@@ -18,44 +24,90 @@ use crate::price_scraper::{GetPriceError, PriceScraper};
 ///////////////////////////////////////////////////////////////////////////////
 // Functions
 
-pub async fn update_all_offers_and_send_notifications(scraper: &PriceScraper, conn: &PgConnection) {
-    //// Prepare data for tasks
-    // Get all offers from database
-    let offers = database::models::offer::queries::all_offers(conn).unwrap();
+/// Checks offers as they get due and plans their next checks. Returns when no offer is due,
+/// unless `run_in_loop` is set, then it never returns. Other instances of the scraper
+/// can run at the same time, every offer is claimed by `worker` before it's checked.
+/// Offers are checked in their own tasks, database calls go to the blocking thread pool.
+pub async fn check_due_offers_and_send_notifications(
+    scraper: Arc<PriceScraper>,
+    pool: PostgresPool,
+    config: Arc<PriceScraperConfig>,
+    worker: String,
+) {
+    let schedule = &config.schedule;
+    let poll_interval = Duration::from_millis(schedule.poll_interval_ms);
+    let stats_interval = Duration::from_secs(schedule.stats_interval_secs);
 
-    // Initialize Stats struct
-    let stats = Rc::new(RefCell::new(Stats {
-        all: offers.len() as u64,
-        ..Default::default()
-    }));
+    let stats = Arc::new(Stats::default());
+    let mut stats_logged_at = Instant::now();
+    // Dropping the set aborts the checks, e.g. when the scraper is stopped
+    let mut running = JoinSet::new();
 
-    // Get last prices of offers
-    let last_prices: Vec<Vec<Price>> = offers
-        .iter()
-        .map(|offer| {
-            database::models::price::queries::get_last_prices_of_offer(conn, offer.id, 72)
-                .unwrap_or_default()
-        })
-        .collect();
+    loop {
+        // Top up offers being checked with the ones which got due
+        if running.len() < schedule.max_concurrent_offers {
+            let limit = (schedule.max_concurrent_offers - running.len()) as i64;
+            let (lease_secs, claiming_worker) = (schedule.lease_secs, worker.clone());
+            let offers = with_connection(&pool, move |conn| {
+                database::models::offer::mutations::claim_due_offers(
+                    conn,
+                    &claiming_worker,
+                    limit,
+                    lease_secs,
+                )
+            })
+            .await;
 
-    // Get products of offers
-    let products: Vec<Vec<Product>> = offers
-        .iter()
-        .map(|offer| {
-            database::models::offer::queries::get_products_of_offer(conn, offer.id)
-                .unwrap_or_default()
-        })
-        .collect();
+            match offers {
+                Ok(offers) => {
+                    stats.all.fetch_add(offers.len() as u64, Relaxed);
+                    for offer in offers {
+                        running.spawn(check_offer(
+                            Arc::clone(&scraper),
+                            pool.clone(),
+                            Arc::clone(&config),
+                            worker.clone(),
+                            offer,
+                            Arc::clone(&stats),
+                        ));
+                    }
+                }
+                Err(error) => error!(
+                    "\n{:?}",
+                    error.attach_printable("Error trying to get due offers")
+                ),
+            }
+        }
+
+        // Downloads are throttled per shop by the scheduler of the scraper
+        if running.is_empty() {
+            if !config.run_in_loop {
+                break;
+            }
+            sleep(poll_interval).await;
+        } else {
+            tokio::select! {
+                result = running.join_next() => {
+                    if let Some(Err(error)) = result {
+                        error!("Checking an offer failed. Cause: {:?}", error);
+                    }
+                }
+                _ = sleep(poll_interval) => {}
+            }
+        }
 
-    // Get handles to async tasks
-    let handles = izip!(offers, last_prices, products).map(|(offer, prices, products)| {
-        update_price_of_offer(scraper, conn, offer, prices, products, Rc::clone(&stats))
-    });
+        if stats_logged_at.elapsed() >= stats_interval {
+            info!("{}", stats);
+            scraper.proxies.log_health();
+            scraper.save_state();
 
-    // Run asynchronously
-    futures::future::join_all(handles).await;
+            stats.reset(running.len() as u64);
+            stats_logged_at = Instant::now();
+        }
+    }
 
-    info!("{}", stats.as_ref().borrow())
+    info!("{}", stats);
+    scraper.proxies.log_health();
 }
 
 ///////////////////////////////////////////////////////////////////////////////
@@ -65,23 +117,67 @@ pub async fn update_all_offers_and_send_notifications(scraper: &PriceScraper, co
 ///////////////////////////////////////////////////////////////////////////////
 // Structures
 
+/// Counted by offer tasks running on any thread.
 #[derive(Default)]
 struct Stats {
-    pub all: u64,
-    pub success: u64,
-    pub price_not_found: u64,
-    pub redirected: u64,
-    pub other_error: u64,
-    pub page_not_supported: u64,
+    pub all: AtomicU64,
+    pub success: AtomicU64,
+    pub out_of_stock: AtomicU64,
+    pub price_not_found: AtomicU64,
+    pub redirected: AtomicU64,
+    pub not_found: AtomicU64,
+    pub blocked: AtomicU64,
+    pub server_error: AtomicU64,
+    pub rate_limited: AtomicU64,
+    pub other_error: AtomicU64,
+    pub page_not_supported: AtomicU64,
+    pub disallowed_by_robots: AtomicU64,
+    /// Part of `success` and `out_of_stock`
+    pub suspicious: AtomicU64,
+    /// Part of `success` and `out_of_stock`
+    pub moved: AtomicU64,
 }
 
 impl Stats {
     fn done(&self) -> u64 {
-        self.success
-            + self.price_not_found
-            + self.redirected
-            + self.other_error
-            + self.page_not_supported
+        [
+            &self.success,
+            &self.out_of_stock,
+            &self.price_not_found,
+            &self.redirected,
+            &self.not_found,
+            &self.blocked,
+            &self.server_error,
+            &self.rate_limited,
+            &self.other_error,
+            &self.page_not_supported,
+            &self.disallowed_by_robots,
+        ]
+        .iter()
+        .map(|counter| counter.load(Relaxed))
+        .sum()
+    }
+
+    /// Starts counting again, `all` being the offers still being checked.
+    fn reset(&self, all: u64) {
+        for counter in [
+            &self.success,
+            &self.out_of_stock,
+            &self.price_not_found,
+            &self.redirected,
+            &self.not_found,
+            &self.blocked,
+            &self.server_error,
+            &self.rate_limited,
+            &self.other_error,
+            &self.page_not_supported,
+            &self.disallowed_by_robots,
+            &self.suspicious,
+            &self.moved,
+        ] {
+            counter.store(0, Relaxed);
+        }
+        self.all.store(all, Relaxed);
     }
 }
 
@@ -93,18 +189,34 @@ impl Display for Stats {
                 "
 Updated {}/{}:
     - {} successfully updated
+        - {} of them suspicious, waiting for a confirmation
+        - {} of them moved to a new url
+    - {} out of stock
     - {} got redirected away (page not found)
+    - {} not found (404)
+    - {} blocked by the shop
+    - {} failed by the shop (5xx)
+    - {} rate limited by the shop
     - {} price not found on given page (product unavailable probably)
     - {} other error while downloading occured
     - {} not supported pages
+    - {} disallowed by robots.txt
 ",
                 self.done(),
-                self.all,
-                self.success,
-                self.redirected,
-                self.price_not_found,
-                self.other_error,
-                self.page_not_supported
+                self.all.load(Relaxed),
+                self.success.load(Relaxed),
+                self.suspicious.load(Relaxed),
+                self.moved.load(Relaxed),
+                self.out_of_stock.load(Relaxed),
+                self.redirected.load(Relaxed),
+                self.not_found.load(Relaxed),
+                self.blocked.load(Relaxed),
+                self.server_error.load(Relaxed),
+                self.rate_limited.load(Relaxed),
+                self.price_not_found.load(Relaxed),
+                self.other_error.load(Relaxed),
+                self.page_not_supported.load(Relaxed),
+                self.disallowed_by_robots.load(Relaxed)
             ),
         )
     }
@@ -126,64 +238,215 @@ enum UpdateOfferError {
 ///////////////////////////////////////////////////////////////////////////////
 // Functions
 
+/// Gets the price of the offer, then plans its next check.
+async fn check_offer(
+    scraper: Arc<PriceScraper>,
+    pool: PostgresPool,
+    config: Arc<PriceScraperConfig>,
+    worker: String,
+    offer: Offer,
+    stats: Arc<Stats>,
+) {
+    let id = offer.id;
+    let history = with_connection(&pool, move |conn| {
+        let prices = database::models::price::queries::get_last_prices_of_offer(conn, id, 72)
+            .unwrap_or_default();
+        let products =
+            database::models::offer::queries::get_products_of_offer(conn, id).unwrap_or_default();
+        let watched =
+            database::models::offer::queries::is_offer_watched(conn, id).unwrap_or_default();
+        Ok::<_, std::convert::Infallible>((prices, products, watched))
+    })
+    .await;
+    let (prices, products, watched) = match history {
+        Ok(v) => v,
+        Err(error) => {
+            error!(
+                "\n{:?}",
+                error
+                    .attach_printable("Error trying to get the history of the offer")
+                    .attach_printable(format!("Offer: {:?}", offer))
+            );
+            return;
+        }
+    };
+
+    let current_interval = offer.check_interval.max(0) as u64;
+    let outcome = update_price_of_offer(&scraper, &pool, offer, prices, products, &stats).await;
+
+    let interval = next_interval(
+        &config.schedule,
+        config.interval,
+        current_interval,
+        outcome,
+        watched,
+    );
+    let next_check_at = chrono::Utc::now().naive_utc() + chrono::Duration::seconds(interval as i64);
+
+    let scheduled = with_connection(&pool, move |conn| {
+        database::models::offer::mutations::schedule_next_check(
+            conn,
+            id,
+            &worker,
+            interval as i32,
+            next_check_at,
+        )
+    })
+    .await;
+    match scheduled {
+        Ok(Some(_)) => {}
+        Ok(None) => log::warn!(
+            "Check took longer than the claim, another worker took the offer. Offer id: {}",
+            id
+        ),
+        Err(error) => error!(
+            "\n{:?}",
+            error
+                .attach_printable("Error trying to plan the next check of the offer")
+                .attach_printable(format!("Offer id: {}", id))
+        ),
+    }
+}
+
 async fn update_price_of_offer(
     scraper: &PriceScraper,
-    conn: &PgConnection,
+    pool: &PostgresPool,
     offer: Offer,
     prices: Vec<Price>,
     products: Vec<Product>,
-    stats: Rc<RefCell<Stats>>,
-) {
+    stats: &Stats,
+) -> CheckOutcome {
+    // Suspicious prices are not compared with until they are confirmed
+    let last_trusted_price = prices
+        .iter()
+        .find(|v| !v.suspicious && v.value.is_some())
+        .and_then(|v| v.value.as_ref());
+
     // Try get price
-    let price_result = scraper
-        .get_price(&offer.url, prices.get(0).and_then(|v| v.value))
-        .await;
+    let price_result = scraper.get_price(&offer.url, last_trusted_price).await;
 
     // Handle result
-    let new_price = match price_result {
+    let (new_price, outcome) = match price_result {
         Ok(v) => {
-            stats.borrow_mut().success += 1;
-            CreatePriceInput {
-                offer_id: offer.id,
-                value: Some(v),
-                availability: Availability::Available,
+            if v.availability == Availability::Available {
+                stats.success.fetch_add(1, Relaxed);
+            } else {
+                stats.out_of_stock.fetch_add(1, Relaxed);
+            }
+
+            if let Some(new_url) = &v.moved_to {
+                stats.moved.fetch_add(1, Relaxed);
+                move_offer(pool, &offer, new_url).await;
+            }
+
+            let mut suspicious = v.suspicious;
+            if suspicious {
+                match confirmed_suspicious_price(scraper, &offer.url, v.value.as_ref(), &prices) {
+                    Some(previous_price) => {
+                        suspicious = false;
+                        confirm_price(pool, previous_price).await;
+                    }
+                    None => {
+                        stats.suspicious.fetch_add(1, Relaxed);
+                    }
+                }
             }
+
+            // Suspicious prices need a confirmation soon
+            let outcome = if suspicious {
+                CheckOutcome::Changed
+            } else {
+                CheckOutcome::of_new_price(
+                    v.value.as_ref(),
+                    v.availability,
+                    v.currency.as_deref(),
+                    &prices,
+                )
+            };
+
+            let new_price = CreatePriceInput {
+                offer_id: offer.id,
+                value: v.value,
+                availability: v.availability,
+                currency: v.currency,
+                suspicious,
+            };
+            (new_price, outcome)
         }
         Err(error) => match error.current_context() {
             GetPriceError::PriceNotFound => {
-                stats.borrow_mut().price_not_found += 1;
+                stats.price_not_found.fetch_add(1, Relaxed);
                 log::warn!("\n{:?}", error);
-                CreatePriceInput {
+                let new_price = CreatePriceInput {
                     offer_id: offer.id,
                     value: None,
                     availability: Availability::PriceNotFound,
-                }
+                    currency: None,
+                    suspicious: false,
+                };
+                (new_price, CheckOutcome::Unavailable)
             }
-            GetPriceError::Redirected => {
-                stats.borrow_mut().redirected += 1;
+            GetPriceError::Redirected | GetPriceError::PageNotFound => {
+                if *error.current_context() == GetPriceError::Redirected {
+                    stats.redirected.fetch_add(1, Relaxed);
+                } else {
+                    stats.not_found.fetch_add(1, Relaxed);
+                }
                 log::warn!("\n{:?}", error);
-                CreatePriceInput {
+                let new_price = CreatePriceInput {
                     offer_id: offer.id,
                     value: None,
                     availability: Availability::SiteNotFound,
-                }
+                    currency: None,
+                    suspicious: false,
+                };
+                (new_price, CheckOutcome::Unavailable)
+            }
+            GetPriceError::Blocked | GetPriceError::ServerError | GetPriceError::RateLimited => {
+                let availability = match error.current_context() {
+                    GetPriceError::Blocked => {
+                        stats.blocked.fetch_add(1, Relaxed);
+                        Availability::Blocked
+                    }
+                    GetPriceError::ServerError => {
+                        stats.server_error.fetch_add(1, Relaxed);
+                        Availability::ServerError
+                    }
+                    _ => {
+                        stats.rate_limited.fetch_add(1, Relaxed);
+                        Availability::RateLimited
+                    }
+                };
+                log::warn!("\n{:?}", error);
+                let new_price = CreatePriceInput {
+                    offer_id: offer.id,
+                    value: None,
+                    availability,
+                    currency: None,
+                    suspicious: false,
+                };
+                (new_price, CheckOutcome::Failed)
             }
             GetPriceError::ErrorDownloadingPage | GetPriceError::PageDownloadTimeout => {
-                stats.borrow_mut().other_error += 1;
+                stats.other_error.fetch_add(1, Relaxed);
                 log::warn!("\n{:?}", error);
-                CreatePriceInput {
+                let new_price = CreatePriceInput {
                     offer_id: offer.id,
                     value: None,
-                    availability: Availability::Unavailable,
-                }
+                    availability: Availability::DownloadFailed,
+                    currency: None,
+                    suspicious: false,
+                };
+                (new_price, CheckOutcome::Failed)
+            }
+            GetPriceError::DisallowedByRobots => {
+                stats.disallowed_by_robots.fetch_add(1, Relaxed);
+                log::warn!("\n{:?}", error);
+                return CheckOutcome::Unavailable;
             }
             GetPriceError::PageNotSupported => {
-                stats.borrow_mut().page_not_supported += 1;
-                debug!(
-                    "Updated {}/{}",
-                    stats.as_ref().borrow().done(),
-                    stats.as_ref().borrow().all
-                );
+                stats.page_not_supported.fetch_add(1, Relaxed);
+                debug!("Updated {}/{}", stats.done(), stats.all.load(Relaxed));
                 error!(
                     "\n{:?}",
                     error
@@ -193,73 +456,161 @@ async fn update_price_of_offer(
                         )
                         .attach_printable(format!("Offer: {:?}", offer))
                 );
-                return;
+                return CheckOutcome::Unavailable;
             }
         },
     };
 
     // Send request to database
-    let db_response = database::models::price::mutations::create_price(conn, &new_price);
+    let price_input = new_price.clone();
+    let db_response = with_connection(pool, move |conn| {
+        database::models::price::mutations::create_price(conn, &price_input)
+    })
+    .await;
 
     // Handle response from database
     let new_price = match db_response {
         Ok(v) => v,
-        Err(err) => {
-            debug!(
-                "Updated {}/{}",
-                stats.as_ref().borrow().done(),
-                stats.as_ref().borrow().all
-            );
+        Err(error) => {
+            debug!("Updated {}/{}", stats.done(), stats.all.load(Relaxed));
             error!(
-                "\n{}",
-                error_stack::report!(UpdateOfferError::DatabaseError)
+                "\n{:?}",
+                error
                     .attach_printable("Error trying to insert new price to database")
                     .attach_printable(format!("Offer: {:?}", offer))
                     .attach_printable(format!("New price to insert: {:?}", new_price))
-                    .attach_printable(format!("Cause: {:?}", err))
             );
-            return;
+            return CheckOutcome::Failed;
         }
     };
 
     debug!(
         "Updated {}/{}: {:?}, {:?} | {}",
-        stats.as_ref().borrow().done(),
-        stats.as_ref().borrow().all,
+        stats.done(),
+        stats.all.load(Relaxed),
         new_price.availability,
         new_price.value,
         offer.url
     );
 
-    send_notification_if_neccesary(conn, &offer, &prices, &products);
+    // Sending emails blocks as well
+    let notified = with_connection(pool, move |conn| {
+        send_notification_if_neccesary(conn, &offer, &new_price, &prices, &products);
+        Ok::<_, std::convert::Infallible>(())
+    })
+    .await;
+    if let Err(error) = notified {
+        error!(
+            "\n{:?}",
+            error.attach_printable("Error trying to send notifications")
+        );
+    }
+
+    outcome
+}
+
+/// The last price was suspicious and the new one is about the same, so the shop really changed it.
+fn confirmed_suspicious_price<'a>(
+    scraper: &PriceScraper,
+    url: &str,
+    new_value: Option<&Decimal>,
+    prices: &'a [Price],
+) -> Option<&'a Price> {
+    let previous_price = prices.first().filter(|v| v.suspicious)?;
+
+    if scraper.is_suspicious_change(url, previous_price.value.as_ref()?, new_value?) {
+        None
+    } else {
+        Some(previous_price)
+    }
+}
+
+async fn confirm_price(pool: &PostgresPool, price: &Price) {
+    let id = price.id;
+    let res = with_connection(pool, move |conn| {
+        database::models::price::mutations::confirm_price(conn, id)
+    })
+    .await;
+
+    if let Err(error) = res {
+        error!(
+            "\n{:?}",
+            error
+                .attach_printable("Error trying to confirm suspicious price")
+                .attach_printable(format!("Price: {:?}", price))
+        );
+    }
+}
+
+/// The shop moved the product to a new url. It fails when another offer has that url already,
+/// the old url keeps working through the redirect anyway.
+async fn move_offer(pool: &PostgresPool, offer: &Offer, new_url: &str) {
+    let (id, url) = (offer.id, new_url.to_owned());
+    let res = with_connection(pool, move |conn| {
+        database::models::offer::mutations::change_url(conn, id, url)
+    })
+    .await;
+
+    match res {
+        Ok(_) => info!(
+            "Offer moved to a new url. Offer: {:?}. New url: {}",
+            offer, new_url
+        ),
+        Err(error) => error!(
+            "\n{:?}",
+            error
+                .attach_printable("Error trying to change url of the offer")
+                .attach_printable(format!("Offer: {:?}", offer))
+                .attach_printable(format!("New url: {}", new_url))
+        ),
+    }
+}
+
+/// Runs blocking diesel calls with a connection from the pool on the blocking thread pool,
+/// so they don't stall downloads running on the same thread.
+async fn with_connection<T, E, F>(
+    pool: &PostgresPool,
+    f: F,
+) -> error_stack::Result<T, UpdateOfferError>
+where
+    F: FnOnce(&PgConnection) -> Result<T, E> + Send + 'static,
+    T: Send + 'static,
+    E: std::fmt::Debug,
+{
+    let pool = pool.clone();
+    tokio::task::spawn_blocking(move || {
+        let conn = pool.get().map_err(|error| {
+            error_stack::report!(error)
+                .change_context(UpdateOfferError::DatabaseError)
+                .attach_printable("Couldn't get a connection from the pool")
+        })?;
+
+        f(&conn).map_err(|error| {
+            error_stack::report!(UpdateOfferError::DatabaseError)
+                .attach_printable(format!("Cause: {:?}", error))
+        })
+    })
+    .await
+    .map_err(|error| {
+        error_stack::report!(error)
+            .change_context(UpdateOfferError::DatabaseError)
+            .attach_printable("Database call panicked")
+    })?
 }
 
+/// `prices` are the prices of the offer from before `new_price` was inserted, newest first.
 fn send_notification_if_neccesary(
     conn: &PgConnection,
     offer: &Offer,
+    new_price: &Price,
     prices: &[Price],
     products_of_offer: &[Product],
 ) {
-    let last_available_prices: Vec<&Price> = prices
-        .iter()
-        .filter(|v| v.availability == Availability::Available)
-        .take(2)
-        .collect();
-
-    let should_send = {
-        if last_available_prices.len() < 2 {
-            false
-        } else {
-            last_available_prices[0].value.unwrap() < last_available_prices[1].value.unwrap()
-        }
+    let previous_price = match notification_reason(new_price, prices) {
+        Some(v) => v.clone(),
+        None => return,
     };
-
-    if !should_send {
-        return;
-    }
-
-    let new_price = last_available_prices[0].clone();
-    let previous_price = last_available_prices[1].clone();
+    let new_price = new_price.clone();
 
     for product in products_of_offer {
         // Get users who are notified about this product from database
@@ -291,3 +642,133 @@ fn send_notification_if_neccesary(
         );
     }
 }
+
+/// Returns the price to compare the new one with in the notification,
+/// when the product went back in stock or became cheaper.
+/// Suspicious prices are ignored, they might come from a broken page.
+fn notification_reason<'a>(new_price: &Price, prices: &'a [Price]) -> Option<&'a Price> {
+    if new_price.availability != Availability::Available || new_price.suspicious {
+        return None;
+    }
+
+    // Back in stock. Other states are errors of scraping, not of the shop, so they are skipped
+    let previous_price = prices
+        .iter()
+        .find(|v| !v.suspicious && v.availability.is_stock_state())?;
+    if matches!(
+        previous_price.availability,
+        Availability::Unavailable | Availability::TemporarilyUnavailable
+    ) {
+        return Some(previous_price);
+    }
+
+    // Price dropped. Prices in different currencies can't be compared
+    let last_available_price = prices
+        .iter()
+        .find(|v| v.availability == Availability::Available && !v.suspicious)?;
+
+    if new_price.currency == last_available_price.currency
+        && new_price.value.as_ref()? < last_available_price.value.as_ref()?
+    {
+        Some(last_available_price)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(value: Option<&str>, availability: Availability) -> Price {
+        Price {
+            currency: value.map(|_| "PLN".to_owned()),
+            id: 0,
+            offer_id: 0,
+            value: value.map(|v| v.parse().unwrap()),
+            created_at: chrono::NaiveDate::from_ymd_opt(2022, 8, 1)
+                .and_then(|v| v.and_hms_opt(12, 0, 0))
+                .unwrap(),
+            availability,
+            suspicious: false,
+        }
+    }
+
+    #[test]
+    fn notifies_when_back_in_stock() {
+        let new_price = price(Some("100.0"), Availability::Available);
+        let prices = vec![price(Some("100.0"), Availability::Unavailable)];
+        assert!(notification_reason(&new_price, &prices).is_some());
+    }
+
+    #[test]
+    fn download_failure_is_not_out_of_stock() {
+        let new_price = price(Some("100.0"), Availability::Available);
+        let prices = vec![
+            price(None, Availability::DownloadFailed),
+            price(Some("100.0"), Availability::Available),
+        ];
+        assert!(notification_reason(&new_price, &prices).is_none());
+
+        let prices = vec![
+            price(None, Availability::DownloadFailed),
+            price(Some("100.0"), Availability::Unavailable),
+        ];
+        assert!(notification_reason(&new_price, &prices).is_some());
+    }
+
+    #[test]
+    fn notifies_when_price_dropped() {
+        let new_price = price(Some("90.0"), Availability::Available);
+        let prices = vec![
+            price(None, Availability::PriceNotFound),
+            price(Some("100.0"), Availability::Available),
+        ];
+        assert_eq!(
+            notification_reason(&new_price, &prices).and_then(|v| v.value.clone()),
+            Some("100.0".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn no_notification_when_currency_changed() {
+        let new_price = Price {
+            currency: Some("EUR".to_owned()),
+            ..price(Some("30.0"), Availability::Available)
+        };
+        let prices = vec![price(Some("100.0"), Availability::Available)];
+        assert!(notification_reason(&new_price, &prices).is_none());
+    }
+
+    #[test]
+    fn no_notification_when_price_rose_or_product_unavailable() {
+        let prices = vec![price(Some("100.0"), Availability::Available)];
+        let new_price = price(Some("110.0"), Availability::Available);
+        assert!(notification_reason(&new_price, &prices).is_none());
+        let new_price = price(Some("90.0"), Availability::Unavailable);
+        assert!(notification_reason(&new_price, &prices).is_none());
+    }
+
+    #[test]
+    fn suspicious_prices_dont_notify() {
+        let suspicious = |value, availability| Price {
+            suspicious: true,
+            ..price(Some(value), availability)
+        };
+
+        let prices = vec![price(Some("100.0"), Availability::Available)];
+        let new_price = suspicious("10.0", Availability::Available);
+        assert!(notification_reason(&new_price, &prices).is_none());
+
+        // Confirmed price is compared with the last trusted one
+        let prices = vec![
+            suspicious("10.0", Availability::Available),
+            price(Some("100.0"), Availability::Available),
+        ];
+        let new_price = price(Some("10.0"), Availability::Available);
+        assert_eq!(
+            notification_reason(&new_price, &prices).and_then(|v| v.value.clone()),
+            Some("100.0".parse().unwrap())
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use env_logger::Env;
 
 pub fn init_env_and_logging() {
@@ -7,3 +9,10 @@ pub fn init_env_and_logging() {
         .write_style_or("MY_LOG_STYLE", "always");
     env_logger::init_from_env(env);
 }
+
+/// Writes the file aside first and renames it, so a crash doesn't leave half of it.
+pub fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let temporary_path = path.with_extension("tmp");
+    std::fs::write(&temporary_path, bytes)?;
+    std::fs::rename(&temporary_path, path)
+}
@@ -0,0 +1,44 @@
+//! Scrapes saved pages of the configured shops, so changes of selectors and parsing
+//! can be checked without network. Pages are recorded with the `record_fixture` binary,
+//! e.g. `cargo run --bin record_fixture -- <product url of every shop>`.
+
+use std::path::{Path, PathBuf};
+
+use database::decimal::Decimal;
+use web_scraper::config::PriceScraperConfig;
+use web_scraper::downloaders::fixture::{load_manifest, FixtureDownloader};
+use web_scraper::price_scraper::PriceScraper;
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+fn config() -> PriceScraperConfig {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../web_scraper_settings");
+    PriceScraperConfig::new_from_file(path.to_str().unwrap())
+}
+
+#[tokio::test]
+async fn fixtures_give_expected_prices() {
+    let dir = fixtures_dir();
+    let scraper = PriceScraper::new(config())
+        .await
+        .with_downloader(Box::new(FixtureDownloader::from_dir(&dir).unwrap()));
+
+    for fixture in load_manifest(&dir).unwrap() {
+        let scraped = match scraper.get_price_once(&fixture.url).await {
+            Ok(v) => v,
+            Err(error) => panic!("Couldn't scrape {}: {:?}", fixture.url, error),
+        };
+
+        let expected_price = fixture.price.map(|v| v.parse::<Decimal>().unwrap());
+        assert_eq!(scraped.value, expected_price, "url: {}", fixture.url);
+        assert_eq!(scraped.currency, fixture.currency, "url: {}", fixture.url);
+        assert_eq!(
+            scraped.availability.to_string(),
+            fixture.availability,
+            "url: {}",
+            fixture.url
+        );
+    }
+}
@@ -24,6 +24,18 @@ impl Mutation {
         models::price::mutations::create_price(conn, &input)
     }
 
+    /// Marks a suspicious price as a real one, e.g. when the shop really has a big sale.
+    #[graphql(name = "confirmPrice")]
+    pub fn confirm_price(context: &GraphQLContext, id: i32) -> FieldResult<Price> {
+        let conn = &context.pool.get()?;
+
+        if context.user_id.is_some() {
+            models::price::mutations::confirm_price(conn, id)
+        } else {
+            Err(FieldError::from("You're not logged in!"))
+        }
+    }
+
     //////////////////////////////////////////////////////////////////////////
     // OFFER
 
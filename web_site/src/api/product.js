@@ -101,8 +101,10 @@ export const getFullProductById = async (id) => {
                         prices {
                             id
                             value
+                            currency
                             createdAt
                             availability
+                            suspicious
                         }
                     }
                 }
@@ -276,6 +278,7 @@ export const addOfferToProduct = async (productId, url) => {
                     prices {
                         id
                         value
+                        currency
                         createdAt
                     }
                 }